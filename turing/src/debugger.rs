@@ -0,0 +1,356 @@
+use crossterm::style::Stylize;
+use turing_core::machine::Block;
+use turing_core::{Machine, RuntimeErrorKind, Status};
+use turing_definitions::ast::SpannedCommand;
+use turing_definitions::diagnostics::SourceMap;
+use turing_definitions::Number;
+
+use crate::repl::{finish_line, print_raw, LineEditor, ReadLine};
+use crate::{machine, runtime_diagnostic, Args, Input, Trace};
+
+const PROMPT: &str = "(debug) ";
+
+static HELP: &str = "\
+s, step            run one command, entering blocks and functions
+n, next            run one command, running blocks and functions to the end
+o, out             run until the current function, or block outside functions, ends
+c, continue        run until a breakpoint or the end of the program
+b, break LINE      stop before the commands on LINE
+b, break NAME      stop when function NAME is called
+d, delete N        delete breakpoint N
+breakpoints        list the breakpoints
+t, tape            show the cells around the head
+bt, stack          show the function call stack
+w, where           show the next command
+h, help            show this help
+q, quit            stop debugging
+An empty line repeats the last command.";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Breakpoint {
+    Line(usize),
+    Function(String),
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "line {}", line),
+            Breakpoint::Function(name) => write!(f, "function {}", name),
+        }
+    }
+}
+
+struct Debugger<'a, T: Number> {
+    machine: Machine<'a, T>,
+    source_map: SourceMap<'a>,
+    file_name: &'a str,
+    breakpoints: Vec<Breakpoint>,
+    finished: bool,
+}
+
+/// Steps through a program in the terminal, reading debugger commands between steps.
+pub(crate) fn debug<T: Number + 'static>(commands: &[SpannedCommand], code: &str, file_name: &str, args: &Args, trace: &Trace) {
+    let mut debugger = Debugger {
+        machine: machine::<T>(commands, args, Input::Terminal, trace),
+        source_map: SourceMap::new(code),
+        file_name,
+        breakpoints: Vec::new(),
+        finished: false,
+    };
+    print_raw("turing debugger, h for help\n");
+    debugger.show_position();
+
+    let mut editor = LineEditor::default();
+    let mut last = String::new();
+    loop {
+        let line = match editor.read_line(PROMPT) {
+            Ok(ReadLine::Line(line)) => line,
+            Ok(ReadLine::Quit) | Err(_) => return,
+        };
+        let line = if line.trim().is_empty() { last.clone() } else { line.trim().to_string() };
+        let mut words = line.splitn(2, char::is_whitespace);
+        let command = words.next().unwrap_or_default();
+        let argument = words.next().map(str::trim).unwrap_or_default();
+        match command {
+            "" => {}
+            "s" | "step" => debugger.step(),
+            "n" | "next" => debugger.next(),
+            "o" | "out" => debugger.out(),
+            "c" | "continue" => debugger.run_until(|_| false),
+            "b" | "break" => debugger.add_breakpoint(argument),
+            "d" | "delete" => debugger.delete_breakpoint(argument),
+            "breakpoints" => debugger.list_breakpoints(),
+            "t" | "tape" => print_raw(&format!("tape {}\n", debugger.machine.snapshot())),
+            "bt" | "stack" => debugger.show_stack(),
+            "w" | "where" => debugger.show_position(),
+            "h" | "help" => print_raw(&format!("{}\n", HELP)),
+            "q" | "quit" => return,
+            command => print_raw(&format!("unknown command `{}`, h for help\n", command)),
+        }
+        last = line;
+    }
+}
+
+impl<'a, T: Number> Debugger<'a, T> {
+    fn step(&mut self) {
+        self.run_until(|_| true);
+    }
+
+    fn next(&mut self) {
+        let depth = self.machine.frames().len();
+        self.run_until(|machine| machine.frames().len() <= depth);
+    }
+
+    fn out(&mut self) {
+        let frames = self.machine.frames();
+        let target = frames.iter().rposition(|frame| frame.block == Block::Function)
+            .unwrap_or(frames.len().saturating_sub(1));
+        if target == 0 {
+            print_raw("not inside a block or function\n");
+            return;
+        }
+        self.run_until(|machine| machine.frames().len() <= target);
+    }
+
+    /// Steps until `stop` holds after a step, a breakpoint is reached or the program ends.
+    fn run_until(&mut self, stop: impl Fn(&Machine<'a, T>) -> bool) {
+        if self.finished {
+            print_raw("the program has finished\n");
+            return;
+        }
+        let mut previous_line = self.current_line();
+        loop {
+            let result = self.machine.step();
+            match result {
+                Ok(Status::Halted) => {
+                    finish_line();
+                    print_raw(&format!("program finished after {} steps\n", self.machine.steps()));
+                    self.finished = true;
+                    return;
+                }
+                Ok(Status::Running) => {
+                    if let Some(index) = self.hit_breakpoint(previous_line) {
+                        finish_line();
+                        print_raw(&format!("breakpoint {}, {}\n", index, self.breakpoints[index]));
+                        break;
+                    }
+                    if stop(&self.machine) {
+                        finish_line();
+                        break;
+                    }
+                    previous_line = self.current_line();
+                }
+                Err(error) if error.kind == RuntimeErrorKind::Interrupted => {
+                    finish_line();
+                    print_raw("interrupted\n");
+                    break;
+                }
+                Err(error) => {
+                    finish_line();
+                    print_raw(&runtime_diagnostic(&error, &self.source_map, self.file_name).render(&self.source_map, self.file_name));
+                    self.finished = true;
+                    return;
+                }
+            }
+        }
+        self.show_position();
+    }
+
+    fn current_line(&self) -> Option<usize> {
+        self.machine.current().map(|command| self.source_map.location(command.start).line)
+    }
+
+    /// A line breakpoint is hit when execution moves onto its line, a function breakpoint when the function is entered.
+    fn hit_breakpoint(&self, previous_line: Option<usize>) -> Option<usize> {
+        let line = self.current_line();
+        let frame = self.machine.frames().last();
+        self.breakpoints.iter().position(|breakpoint| match breakpoint {
+            Breakpoint::Line(n) => line == Some(*n) && previous_line != Some(*n),
+            Breakpoint::Function(name) => frame
+                .is_some_and(|frame| frame.function == Some(name.as_str()) && frame.index() == 0),
+        })
+    }
+
+    fn add_breakpoint(&mut self, argument: &str) {
+        let breakpoint = match argument.parse::<usize>() {
+            Ok(line) => Breakpoint::Line(line),
+            Err(_) if !argument.is_empty() => Breakpoint::Function(argument.to_string()),
+            Err(_) => {
+                print_raw("usage: break LINE or break NAME\n");
+                return;
+            }
+        };
+        print_raw(&format!("breakpoint {}, {}\n", self.breakpoints.len(), breakpoint));
+        self.breakpoints.push(breakpoint);
+    }
+
+    fn delete_breakpoint(&mut self, argument: &str) {
+        match argument.parse::<usize>() {
+            Ok(index) if index < self.breakpoints.len() => {
+                self.breakpoints.remove(index);
+            }
+            _ => print_raw(&format!("no breakpoint `{}`\n", argument)),
+        }
+    }
+
+    fn list_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            print_raw("no breakpoints\n");
+        }
+        for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+            print_raw(&format!("{}: {}\n", index, breakpoint));
+        }
+    }
+
+    fn show_stack(&self) {
+        let stack = self.machine.call_stack();
+        for (depth, frame) in stack.iter().enumerate() {
+            let location = self.source_map.location(frame.start);
+            print_raw(&format!("#{} {} called at {}:{}:{}\n", depth, frame.function, self.file_name, location.line, location.column));
+        }
+        print_raw(&format!("#{} <program>\n", stack.len()));
+    }
+
+    /// Shows the next command highlighted in its line, followed by the tape around the head.
+    fn show_position(&self) {
+        let Some(command) = self.machine.current() else {
+            print_raw("the program has finished\n");
+            return;
+        };
+        let location = self.source_map.location(command.start);
+        print_raw(&format!(
+            "{}:{}:{}\n{} | {}\n",
+            self.file_name, location.line, location.column,
+            location.line, highlight_line(&self.source_map, command),
+        ));
+        print_raw(&format!("tape {}\n", self.machine.snapshot()));
+    }
+}
+
+/// The line `command` starts on, with the command shown in reverse video.
+pub(crate) fn highlight_line(source_map: &SourceMap, command: &SpannedCommand) -> String {
+    let location = source_map.location(command.start);
+    let line = source_map.line(location.line);
+    let start = location.column - 1;
+    let end = source_map.location(command.end);
+    let len = if end.line == location.line { end.column - location.column } else { line.chars().count() - start };
+    let before: String = line.chars().take(start).collect();
+    let span: String = line.chars().skip(start).take(len.max(1)).collect();
+    let after: String = line.chars().skip(start + len.max(1)).collect();
+    format!("{}{}{}", before, span.reverse().bold(), after)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use turing_core::io::MemoryIo;
+    use turing_core::Config;
+    use turing_definitions::parser::parse;
+
+    fn debugger<'a>(commands: &'a [SpannedCommand<'a>], code: &'a str) -> Debugger<'a, i64> {
+        Debugger {
+            machine: Machine::new(commands, Config::default()).with_io(Rc::new(RefCell::new(MemoryIo::new("")))),
+            source_map: SourceMap::new(code),
+            file_name: "test.txt",
+            breakpoints: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// The source of the next command.
+    fn current<'a>(debugger: &Debugger<'a, i64>, code: &'a str) -> &'a str {
+        debugger.machine.current().map_or("", |command| &code[command.start..command.end])
+    }
+
+    /// Steps until the next command is `text`.
+    fn step_to(debugger: &mut Debugger<i64>, code: &str, text: &str) {
+        while current(debugger, code) != text {
+            assert!(!debugger.finished, "the program finished before reaching {:?}", text);
+            debugger.step();
+        }
+    }
+
+    #[test]
+    fn test_line_breakpoints() {
+        let code = "1\n2 2\nwhile - end\n3";
+        let commands = parse(code).unwrap();
+        let mut debugger = debugger(&commands, code);
+        debugger.add_breakpoint("2");
+        debugger.run_until(|_| false);
+        assert_eq!(debugger.current_line(), Some(2));
+        assert_eq!(debugger.machine.steps(), 1);
+        // Staying on the line does not hit the breakpoint again.
+        debugger.run_until(|_| false);
+        assert!(debugger.finished);
+
+        let mut debugger = self::debugger(&commands, code);
+        debugger.add_breakpoint("3");
+        debugger.delete_breakpoint("0");
+        debugger.run_until(|_| false);
+        assert!(debugger.finished);
+    }
+
+    #[test]
+    fn test_function_breakpoints() {
+        let code = "fun f\n  1\nend\nf 4 f";
+        let commands = parse(code).unwrap();
+        let mut debugger = debugger(&commands, code);
+        debugger.add_breakpoint("f");
+        for _ in 0..2 {
+            debugger.run_until(|_| false);
+            assert_eq!(debugger.machine.frames().last().and_then(|frame| frame.function), Some("f"));
+            assert_eq!(current(&debugger, code), "1");
+        }
+        debugger.run_until(|_| false);
+        assert!(debugger.finished);
+    }
+
+    #[test]
+    fn test_next_runs_calls_and_blocks() {
+        let code = "fun f\n  1 while - end\n  2\nend\nf 9";
+        let commands = parse(code).unwrap();
+        let mut debugger = debugger(&commands, code);
+        step_to(&mut debugger, code, "f");
+        let depth = debugger.machine.frames().len();
+        debugger.next();
+        assert_eq!(current(&debugger, code), "9");
+        assert_eq!(debugger.machine.frames().len(), depth);
+
+        let mut debugger = self::debugger(&commands, code);
+        step_to(&mut debugger, code, "f");
+        debugger.step();
+        assert_eq!(current(&debugger, code), "1");
+        assert_eq!(debugger.machine.frames().len(), depth + 1);
+        step_to(&mut debugger, code, "while - end");
+        debugger.next();
+        assert_eq!(current(&debugger, code), "2");
+    }
+
+    #[test]
+    fn test_out() {
+        let code = "fun f\n  1 while - end\n  2\nend\nf 9";
+        let commands = parse(code).unwrap();
+        let mut debugger = debugger(&commands, code);
+        step_to(&mut debugger, code, "-");
+        // Inside a block inside a function, out leaves the function.
+        debugger.out();
+        assert_eq!(current(&debugger, code), "9");
+
+        // Outside any block or function there is nothing to leave.
+        let steps = debugger.machine.steps();
+        debugger.out();
+        assert_eq!(debugger.machine.steps(), steps);
+        assert_eq!(current(&debugger, code), "9");
+
+        let code = "3 while - end 7";
+        let commands = parse(code).unwrap();
+        let mut debugger = self::debugger(&commands, code);
+        step_to(&mut debugger, code, "-");
+        debugger.out();
+        assert_eq!(current(&debugger, code), "7");
+    }
+}
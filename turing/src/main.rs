@@ -1,62 +1,174 @@
-use std::io::Write;
-use crossterm::event::{read, DisableBracketedPaste, EnableBracketedPaste, Event};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, IsTerminal, Read};
+use std::rc::Rc;
+use std::time::Duration;
+use crossterm::event::{DisableBracketedPaste, EnableBracketedPaste};
 use crossterm::execute;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-use interpreter::Interpreter;
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use turing_definitions::ast::SpannedCommand;
+use turing_definitions::diagnostics::{Diagnostic, SourceMap};
+use turing_definitions::resolve::resolve;
+use turing_definitions::tape::{BoundedTape, InfiniteTape, SparseTape};
+use turing_definitions::{Number, OverflowMode, Tape};
 
-use crate::interpreter::InterpreterUtils;
+use turing_core::io::{Io, StreamIo};
+use turing_core::machine::DEFAULT_MAX_CALL_DEPTH;
+use turing_core::trace::JsonLinesTracer;
+use turing_core::{compile, compile_optimized, Config, Machine, RuntimeError, RuntimeErrorKind, Vm};
 
-pub mod interpreter;
+use crate::terminal::TerminalIo;
+
+pub mod debugger;
+pub mod repl;
+pub mod terminal;
+pub mod visualizer;
+
+/// Exit code for a program stopped by `--max-steps` or `--timeout`.
+const LIMIT_EXIT_CODE: i32 = 2;
+
+/// The modulus for `--overflow modular` without `--modulus`, or the largest that fits the cell type if smaller.
+const DEFAULT_MODULUS: u64 = 256;
+
+/// The most function calls listed under a runtime error.
+const STACK_TRACE_LENGTH: usize = 10;
 
 #[derive(Parser)]
 #[command(name = "turing", version = "0.1.0", about = "A simple turing machine interpreter")]
 struct Args {
-    #[arg(short, long)]
+    #[command(subcommand)]
+    mode: Option<Mode>,
+    #[arg(short, long, global = true)]
     file: Option<String>,
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     manual: bool,
+    #[arg(short, long, value_enum, default_value_t = CellType::I64, global = true)]
+    cell_type: CellType,
+    #[arg(short, long, value_enum, default_value_t = Overflow::Wrap, global = true)]
+    overflow: Overflow,
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), global = true)]
+    modulus: Option<u64>,
+    #[arg(short, long, value_enum, default_value_t = TapeKind::Vec, global = true)]
+    tape: TapeKind,
+    #[arg(long, value_name = "LIMIT", value_parser = parse_tape_limit, global = true)]
+    max_tape: Option<TapeLimit>,
+    #[arg(long, value_name = "STEPS", global = true)]
+    max_steps: Option<u64>,
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout, global = true)]
+    timeout: Option<Duration>,
+    /// The most function calls that can be nested before the program stops with a stack overflow
+    #[arg(long, value_name = "DEPTH", default_value_t = DEFAULT_MAX_CALL_DEPTH, global = true)]
+    max_depth: usize,
+    /// Read program input from FILE instead of the keyboard
+    #[arg(short, long, value_name = "FILE", global = true)]
+    input: Option<String>,
+    /// The value `?` writes once the input has run out
+    #[arg(long, value_name = "VALUE", default_value_t = 0, allow_negative_numbers = true, global = true)]
+    eof: i64,
+    /// Record every step to FILE as JSON Lines
+    #[arg(long, value_name = "FILE", global = true)]
+    trace: Option<String>,
 }
 
+/// The `--trace` file, shared by every machine the REPL creates.
+type Trace = Option<Rc<RefCell<JsonLinesTracer<BufWriter<File>>>>>;
+type SharedTape<T> = Rc<RefCell<dyn Tape<T>>>;
 
+#[derive(Subcommand)]
+enum Mode {
+    /// Step through a program with breakpoints
+    Debug,
+    /// Animate a program, showing the tape around the head
+    Visualize,
+}
 
-fn read_keys(buffer: &mut String) {
-    loop {
-        match read().unwrap() {
-            Event::Key(event) => {
-                match event.code {
-                    crossterm::event::KeyCode::Enter => {
-                        println!("\r");
-                        break;
-                    }
-                    crossterm::event::KeyCode::Backspace => {
-                        print!("\x08 \x08");
-                        std::io::stdout().flush().unwrap();
-                        buffer.pop();
-                    }
-                    crossterm::event::KeyCode::Char(c) => {
-                        if c == 'c' && event.modifiers == crossterm::event::KeyModifiers::CONTROL {
-                            shutdown();
-                            return;
-                        }
-                        print!("{}", c);
-                        std::io::stdout().flush().unwrap();
-                        buffer.push(c);
-                    }
-                    _ => {}
-                }
-            }
-            Event::Paste(text) => {
-                println!("Paste: {}\r", text);
-                buffer.push_str(&text);
-                break;
-            }
-            _ => {}
-        }
+/// Where `?` reads from.
+enum Input {
+    /// Key presses in a raw mode terminal.
+    Terminal,
+    /// Stdin when it is not a terminal, such as a pipe.
+    Stdin,
+    File(File),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum CellType {
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Overflow {
+    Wrap,
+    Saturate,
+    Checked,
+    Modular,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum TapeKind {
+    Vec,
+    Infinite,
+    Sparse,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum TapeLimit {
+    Cells(usize),
+    Bytes(usize),
+}
+
+fn parse_timeout(seconds: &str) -> Result<Duration, String> {
+    let seconds: f64 = seconds.parse().map_err(|_| format!("`{}` is not a number of seconds", seconds))?;
+    Duration::try_from_secs_f64(seconds).map_err(|error| error.to_string())
+}
+
+/// Parses a cell count such as `100000`, or a byte budget with a unit such as `512KB` or `64MiB`.
+fn parse_tape_limit(limit: &str) -> Result<TapeLimit, String> {
+    let split = limit.find(|c: char| !c.is_ascii_digit()).unwrap_or(limit.len());
+    let (number, unit) = limit.split_at(split);
+    let number: usize = number.parse().map_err(|_| format!("`{}` does not start with a number", limit))?;
+    let multiplier: usize = match unit.trim() {
+        "" => return Ok(TapeLimit::Cells(number)),
+        "B" => 1,
+        "KB" => 1000,
+        "KiB" => 1 << 10,
+        "MB" => 1000 * 1000,
+        "MiB" => 1 << 20,
+        "GB" => 1000 * 1000 * 1000,
+        "GiB" => 1 << 30,
+        unit => return Err(format!("unknown unit `{}`, expected B, KB, KiB, MB, MiB, GB or GiB", unit)),
+    };
+    number.checked_mul(multiplier)
+        .map(TapeLimit::Bytes)
+        .ok_or_else(|| format!("`{}` is too large", limit))
+}
 
+/// Rejects a `--modulus` whose results would not fit in the chosen cell type.
+fn check_modulus(args: &Args) -> Result<(), String> {
+    let max_modulus = match args.cell_type {
+        CellType::I8 => i8::max_modulus(),
+        CellType::I16 => i16::max_modulus(),
+        CellType::I32 => i32::max_modulus(),
+        CellType::I64 => i64::max_modulus(),
+        CellType::F32 => f32::max_modulus(),
+        CellType::F64 => f64::max_modulus(),
+    };
+    match args.modulus {
+        Some(modulus) if args.overflow == Overflow::Modular && modulus > max_modulus => {
+            Err(format!("modulus {} does not fit the cell type, the largest is {}", modulus, max_modulus))
+        }
+        _ => Ok(()),
     }
 }
 
+
+
 pub fn shutdown() {
     disable_raw_mode().unwrap();
     execute!(std::io::stdout(),
@@ -67,48 +179,233 @@ pub fn shutdown() {
 fn main() {
 
     let args = Args::parse();
+    if let Err(message) = check_modulus(&args) {
+        Args::command().error(clap::error::ErrorKind::ValueValidation, message).exit();
+    }
 
     if args.manual {
         println!("{}", MANUAL);
         return;
     }
 
-    execute!(std::io::stdout(),
-             EnableBracketedPaste,
-    ).unwrap();
-    enable_raw_mode().unwrap();
+    let input = match &args.input {
+        Some(path) => match File::open(path) {
+            Ok(file) => Input::File(file),
+            Err(error) => {
+                eprintln!("error: cannot open input `{}`: {}", path, error);
+                std::process::exit(1);
+            }
+        },
+        None if std::io::stdin().is_terminal() => Input::Terminal,
+        None => Input::Stdin,
+    };
+    let interactive = matches!(input, Input::Terminal);
+
+    let trace: Trace = match &args.trace {
+        Some(path) => match File::create(path) {
+            Ok(file) => Some(Rc::new(RefCell::new(JsonLinesTracer::new(BufWriter::new(file))))),
+            Err(error) => {
+                eprintln!("error: cannot create trace `{}`: {}", path, error);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    if let Some(mode) = &args.mode {
+        if args.file.is_none() || !interactive {
+            let name = match mode {
+                Mode::Debug => "debug",
+                Mode::Visualize => "visualize",
+            };
+            eprintln!("error: `turing {}` needs a program given with --file and a terminal for input", name);
+            std::process::exit(1);
+        }
+    }
 
-    std::panic::set_hook(Box::new(|panic_info| {
-        disable_raw_mode().unwrap();
+    if interactive {
         execute!(std::io::stdout(),
-                 DisableBracketedPaste,
+                 EnableBracketedPaste,
         ).unwrap();
-        eprintln!("{}", panic_info);
-    }));
+        enable_raw_mode().unwrap();
+
+        std::panic::set_hook(Box::new(|panic_info| {
+            disable_raw_mode().unwrap();
+            execute!(std::io::stdout(),
+                     DisableBracketedPaste,
+            ).unwrap();
+            eprintln!("{}", panic_info);
+        }));
+    }
     
     
-    let code = if let Some(file) = args.file {
+    if interactive && args.file.is_none() {
+        match args.cell_type {
+            CellType::I8 => repl::repl::<i8>(&args, &trace),
+            CellType::I16 => repl::repl::<i16>(&args, &trace),
+            CellType::I32 => repl::repl::<i32>(&args, &trace),
+            CellType::I64 => repl::repl::<i64>(&args, &trace),
+            CellType::F32 => repl::repl::<f32>(&args, &trace),
+            CellType::F64 => repl::repl::<f64>(&args, &trace),
+        }
+        shutdown();
+        return;
+    }
+
+    let file_name = args.file.clone().unwrap_or_else(|| String::from("<input>"));
+    let code = if let Some(file) = &args.file {
         std::fs::read_to_string(file).unwrap()
     } else {
-        let mut line = String::new();
-        println!("\r\nEnter your code: \r");
-        read_keys(&mut line);
-        //std::io::stdin().read_line(&mut line).unwrap();
-        line
+        let mut code = String::new();
+        std::io::stdin().read_to_string(&mut code).unwrap();
+        code
     };
 
-    //disable_raw_mode().unwrap();
-
-    let commands = turing_definitions::parser::parse(&code);
+    let parsed = turing_definitions::parser::parse(&code)
+        .and_then(|commands| resolve(&commands, &[]).map(|()| commands));
+    let commands = match parsed {
+        Ok(commands) => commands,
+        Err(errors) => {
+            if interactive {
+                shutdown();
+            }
+            let source_map = SourceMap::new(&code);
+            for error in &errors {
+                eprint!("{}", Diagnostic::from(error).render(&source_map, &file_name));
+            }
+            std::process::exit(1);
+        }
+    };
     
-    //println!("{:#?}", commands);
+    if let Some(Mode::Debug) = args.mode {
+        match args.cell_type {
+            CellType::I8 => debugger::debug::<i8>(&commands, &code, &file_name, &args, &trace),
+            CellType::I16 => debugger::debug::<i16>(&commands, &code, &file_name, &args, &trace),
+            CellType::I32 => debugger::debug::<i32>(&commands, &code, &file_name, &args, &trace),
+            CellType::I64 => debugger::debug::<i64>(&commands, &code, &file_name, &args, &trace),
+            CellType::F32 => debugger::debug::<f32>(&commands, &code, &file_name, &args, &trace),
+            CellType::F64 => debugger::debug::<f64>(&commands, &code, &file_name, &args, &trace),
+        }
+        shutdown();
+        return;
+    }
 
-    //enable_raw_mode().unwrap();
-    
-    let mut interpreter = Interpreter::new_vec_tape();
-    interpreter.interpret_commands(&commands);
+    if let Some(Mode::Visualize) = args.mode {
+        match args.cell_type {
+            CellType::I8 => visualizer::visualize::<i8>(&commands, &code, &file_name, &args, &trace),
+            CellType::I16 => visualizer::visualize::<i16>(&commands, &code, &file_name, &args, &trace),
+            CellType::I32 => visualizer::visualize::<i32>(&commands, &code, &file_name, &args, &trace),
+            CellType::I64 => visualizer::visualize::<i64>(&commands, &code, &file_name, &args, &trace),
+            CellType::F32 => visualizer::visualize::<f32>(&commands, &code, &file_name, &args, &trace),
+            CellType::F64 => visualizer::visualize::<f64>(&commands, &code, &file_name, &args, &trace),
+        }
+        shutdown();
+        return;
+    }
+
+    let source_map = SourceMap::new(&code);
+    let result = match args.cell_type {
+        CellType::I8 => run::<i8>(&commands, &args, input, &trace, &source_map, &file_name),
+        CellType::I16 => run::<i16>(&commands, &args, input, &trace, &source_map, &file_name),
+        CellType::I32 => run::<i32>(&commands, &args, input, &trace, &source_map, &file_name),
+        CellType::I64 => run::<i64>(&commands, &args, input, &trace, &source_map, &file_name),
+        CellType::F32 => run::<f32>(&commands, &args, input, &trace, &source_map, &file_name),
+        CellType::F64 => run::<f64>(&commands, &args, input, &trace, &source_map, &file_name),
+    };
+
+    if interactive {
+        shutdown();
+    }
+
+    if let Err((diagnostic, exit_code)) = result {
+        eprint!("\n{}", diagnostic.render(&source_map, &file_name));
+        std::process::exit(exit_code);
+    }
+}
+
+/// Runs the program, returning the diagnostic and exit code for a runtime error.
+///
+/// Programs run compiled to bytecode, unless they are traced, which needs the step by step [`Machine`]. The bytecode is
+/// optimized unless the steps are limited, since optimized programs take fewer steps.
+fn run<T: Number + 'static>(commands: &[SpannedCommand], args: &Args, input: Input, trace: &Trace, source_map: &SourceMap, file_name: &str) -> Result<(), (Diagnostic, i32)> {
+    let result = if trace.is_some() {
+        machine::<T>(commands, args, input, trace).run()
+    } else {
+        let (tape, io, config) = setup::<T>(args, input);
+        let program = match config.max_steps {
+            Some(_) => compile(commands),
+            None => compile_optimized(commands, config.overflow_mode),
+        };
+        Vm::new(program, config).with_tape(tape).with_io(io).run()
+    };
+    match result {
+        Err(error) if error.kind == RuntimeErrorKind::Interrupted => Ok(()),
+        result => result,
+    }.map_err(|error| {
+        let exit_code = if error.kind.is_limit() { LIMIT_EXIT_CODE } else { 1 };
+        (runtime_diagnostic(&error, source_map, file_name), exit_code)
+    })
+}
 
-    shutdown();
+/// Builds a machine for `commands` with the tape and limits chosen on the command line.
+fn machine<'a, T: Number + 'static>(commands: &'a [SpannedCommand<'a>], args: &Args, input: Input, trace: &Trace) -> Machine<'a, T> {
+    let (tape, io, config) = setup::<T>(args, input);
+    let machine = Machine::new(commands, config).with_tape(tape).with_io(io);
+    match trace {
+        Some(tracer) => machine.with_tracer(tracer.clone()),
+        None => machine,
+    }
+}
+
+/// The tape, io and config chosen on the command line.
+fn setup<T: Number + 'static>(args: &Args, input: Input) -> (SharedTape<T>, Rc<RefCell<dyn Io>>, Config<T>) {
+    let tape = match args.tape {
+        TapeKind::Vec => limit_tape(vec![T::default()], args.max_tape),
+        TapeKind::Infinite => limit_tape(InfiniteTape::<T>::new(), args.max_tape),
+        TapeKind::Sparse => limit_tape(SparseTape::<T>::new(), args.max_tape),
+    };
+    let io: Rc<RefCell<dyn Io>> = match input {
+        Input::Terminal => Rc::new(RefCell::new(TerminalIo)),
+        Input::Stdin => Rc::new(RefCell::new(StreamIo::stdio())),
+        Input::File(file) => Rc::new(RefCell::new(StreamIo::new(BufReader::new(file), BufWriter::new(std::io::stdout())))),
+    };
+    let overflow_mode = match args.overflow {
+        Overflow::Wrap => OverflowMode::Wrapping,
+        Overflow::Saturate => OverflowMode::Saturating,
+        Overflow::Checked => OverflowMode::Checked,
+        Overflow::Modular => OverflowMode::Modular(args.modulus.unwrap_or(DEFAULT_MODULUS.min(T::max_modulus()))),
+    };
+    let config = Config {
+        overflow_mode,
+        max_steps: args.max_steps,
+        timeout: args.timeout,
+        max_call_depth: args.max_depth,
+        eof_value: T::from(args.eof),
+    };
+    (tape, io, config)
+}
+
+fn limit_tape<T: Number + 'static, P: Tape<T> + 'static>(tape: P, limit: Option<TapeLimit>) -> SharedTape<T> {
+    match limit {
+        None => Rc::new(RefCell::new(tape)),
+        Some(TapeLimit::Cells(max_cells)) => Rc::new(RefCell::new(BoundedTape::new(tape, max_cells))),
+        Some(TapeLimit::Bytes(max_bytes)) => Rc::new(RefCell::new(BoundedTape::with_max_bytes::<T>(tape, max_bytes))),
+    }
+}
+
+/// Describes a runtime error, with the tape around the head and the functions that were being run.
+fn runtime_diagnostic<T: Number>(error: &RuntimeError<T>, source_map: &SourceMap, file_name: &str) -> Diagnostic {
+    let mut diagnostic = Diagnostic::error(error.to_string(), error.start, error.end)
+        .with_note(format!("tape {}", error.tape));
+    // Runaway recursion can leave thousands of frames, so only the innermost are listed.
+    for frame in error.stack.iter().take(STACK_TRACE_LENGTH) {
+        let location = source_map.location(frame.start);
+        diagnostic = diagnostic.with_note(format!("in `{}`, called at {}:{}:{}", frame.function, file_name, location.line, location.column));
+    }
+    if error.stack.len() > STACK_TRACE_LENGTH {
+        diagnostic = diagnostic.with_note(format!("... and {} more calls", error.stack.len() - STACK_TRACE_LENGTH));
+    }
+    diagnostic
 }
 
 
@@ -116,19 +413,22 @@ static MANUAL: &str = r#"
 Turing Machine Simulator
 Numbers:
   - Numbers move the tape head over by one and set that cell to the number
+  - Numbers can be negative (-1), hexadecimal (0xFF), binary (0b1010) or a character ('A')
+  - Numbers with a decimal point or exponent (3.5, 1e3) are floats and are truncated on integer tapes
   - Example: 5
 Strings:
   - Strings move the tape head over by each character and set that cell to the UTF32 value of the character
+  - Escape sequences \n, \t, \r, \0, \\, \" and \u{...} are supported
   - Example: "Hello"
 Writing in Place:
   - Writing in place rather than moving the tape head is done by wrapping the value in brackets.
   - List will still move the tape head
   - Example: [5]
 Commands:
-  - <: Move the tape head to the left
+  - <: Move the tape head to the left (it stays on cell 0 unless run with --tape infinite)
   - >: Move the tape head to the right
-  - <|: Move the tape head to the left by the value of the current cell
-  - |>: Move the tape head to the right by the value of the current cell
+  - <|: Move the tape head to the left by the value of the current cell, or right if it is negative
+  - |>: Move the tape head to the right by the value of the current cell, or left if it is negative
   - <#): Move the tape head to the left by #
   - (#>): Move the tape head to the right by #
   - +: Increment the value of the current cell
@@ -146,13 +446,81 @@ Control Flow:
 Functions:
   - fun name: Define a function with the name name
   - end: End the function
-  - getfun name: Get the index of the function with the name name
-  - call: Call the function at the index of the current cell
+  - getfun name: Write the index of the function with the name name to the current cell
+  - call: Call the function whose index is in the current cell, stopping with an error if there is none
+  - Functions defined outside of any block can be called before their definition
+  - Calling or using getfun on a name that no fun in the program defines is an error before the program runs
+  - A fun inside a block or another function only defines its name once it runs, and using the name before that stops the program
 Printing:
   - .: (Period) Print the value of the current cell as a number
-  - ,: (Comma) Print the value of the current cell as a character
+  - ,: (Comma) Print the value of the current cell as a character, or a space if it is negative or not a character
 User Input:
   - ?: (Question Mark) Get a keypress from the user and set the current cell to the UTF32 value of the key
+  - When stdin is not a terminal, or with --input FILE, ? reads the next character instead
+  - Once the input runs out, ? sets the current cell to 0, or the value given with --eof
 Quitting:
   - c^c: (Control-C) Quit the program
 See this website for more information: https://killerrabbit.xyz/pl/turing/"#;
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cell_type() {
+        let args = Args::try_parse_from(["turing"]).unwrap();
+        assert!(args.cell_type == CellType::I64);
+        for (name, cell_type) in [("i8", CellType::I8), ("i16", CellType::I16), ("i32", CellType::I32), ("f32", CellType::F32), ("f64", CellType::F64)] {
+            let args = Args::try_parse_from(["turing", "--cell-type", name]).unwrap();
+            assert!(args.cell_type == cell_type);
+        }
+        assert!(Args::try_parse_from(["turing", "--cell-type", "u8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_timeout("0.25"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_timeout("soon"), Err(String::from("`soon` is not a number of seconds")));
+        assert!(parse_timeout("-1").is_err());
+    }
+
+    #[test]
+    fn test_limit_exit_code() {
+        let exit_code = |program: &str, arguments: &[&str]| {
+            let args = Args::try_parse_from([&["turing"], arguments].concat()).unwrap();
+            let commands = turing_definitions::parser::parse(program).unwrap();
+            let source_map = SourceMap::new(program);
+            run::<i64>(&commands, &args, Input::Stdin, &None, &source_map, "test").err().map(|(_, exit_code)| exit_code)
+        };
+        assert_eq!(exit_code("1 while end", &["--max-steps", "50"]), Some(LIMIT_EXIT_CODE));
+        assert_eq!(exit_code("1 while end", &["--timeout", "0.01"]), Some(LIMIT_EXIT_CODE));
+        assert_eq!(exit_code("0 <%", &["--max-steps", "50"]), Some(1));
+        assert_eq!(exit_code("3 while - end", &["--max-steps", "50"]), None);
+    }
+
+    #[test]
+    fn test_check_modulus() {
+        let args = |arguments: &[&str]| Args::try_parse_from([&["turing", "--overflow", "modular"], arguments].concat()).unwrap();
+        assert_eq!(check_modulus(&args(&["-c", "i8", "--modulus", "128"])), Ok(()));
+        assert_eq!(check_modulus(&args(&["-c", "i8"])), Ok(()));
+        assert_eq!(
+            check_modulus(&args(&["-c", "i8", "--modulus", "1000"])),
+            Err(String::from("modulus 1000 does not fit the cell type, the largest is 128")),
+        );
+        assert!(check_modulus(&args(&["-c", "i64", "--modulus", "18446744073709551615"])).is_err());
+        assert_eq!(check_modulus(&args(&["-c", "f32", "--modulus", "18446744073709551615"])), Ok(()));
+
+        // The modulus only matters with modular overflow.
+        let args = Args::try_parse_from(["turing", "-c", "i8", "--modulus", "1000"]).unwrap();
+        assert_eq!(check_modulus(&args), Ok(()));
+    }
+
+    #[test]
+    fn test_default_modulus() {
+        let args = Args::try_parse_from(["turing", "--overflow", "modular"]).unwrap();
+        assert_eq!(setup::<i8>(&args, Input::Stdin).2.overflow_mode, OverflowMode::Modular(128));
+        assert_eq!(setup::<i64>(&args, Input::Stdin).2.overflow_mode, OverflowMode::Modular(256));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use crossterm::cursor::{position, MoveToColumn};
+use crossterm::event::{read, Event, KeyCode, KeyModifiers};
+use crossterm::queue;
+use crossterm::style::Print;
+use crossterm::terminal::{Clear, ClearType};
+use typed_arena::Arena;
+use turing_core::{Machine, RuntimeErrorKind};
+use turing_definitions::ast::SpannedCommand;
+use turing_definitions::diagnostics::{Diagnostic, SourceMap};
+use turing_definitions::parser::{parse, ParseErrorKind};
+use turing_definitions::resolve::resolve;
+use turing_definitions::Number;
+
+use crate::{machine, runtime_diagnostic, Args, Input, Trace};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = ". ";
+const FILE_NAME: &str = "<repl>";
+
+static HELP: &str = "\
+:tape        show the cells around the head
+:head        show the position of the head
+:funcs       list the defined functions and their indices
+:reset       clear the tape, head and functions
+:load FILE   run a file, keeping the functions it defines
+:help        show this help
+:quit        leave the REPL (or Ctrl-C, or Ctrl-D on an empty line)";
+
+pub(crate) enum ReadLine {
+    Line(String),
+    Quit,
+}
+
+/// Reads lines from a terminal in raw mode, with history and cursor movement.
+#[derive(Default)]
+pub(crate) struct LineEditor {
+    history: Vec<String>,
+    /// Lines of a multi-line paste that have not been read yet.
+    pending: VecDeque<String>,
+}
+
+impl LineEditor {
+    pub(crate) fn read_line(&mut self, prompt: &str) -> std::io::Result<ReadLine> {
+        if let Some(line) = self.pending.pop_front() {
+            print_raw(&format!("{}{}\n", prompt, line));
+            self.history.push(line.clone());
+            return Ok(ReadLine::Line(line));
+        }
+
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        let mut history_index = self.history.len();
+        let mut draft = Vec::new();
+        draw(prompt, &line, cursor)?;
+        loop {
+            match read()? {
+                Event::Key(event) => match event.code {
+                    KeyCode::Enter => break,
+                    KeyCode::Char('c') if event.modifiers == KeyModifiers::CONTROL => {
+                        print_raw("\n");
+                        return Ok(ReadLine::Quit);
+                    }
+                    KeyCode::Char('d') if event.modifiers == KeyModifiers::CONTROL && line.is_empty() => {
+                        print_raw("\n");
+                        return Ok(ReadLine::Quit);
+                    }
+                    KeyCode::Char(_) if event.modifiers == KeyModifiers::CONTROL => {}
+                    KeyCode::Char(c) => {
+                        line.insert(cursor, c);
+                        cursor += 1;
+                    }
+                    KeyCode::Backspace if cursor > 0 => {
+                        cursor -= 1;
+                        line.remove(cursor);
+                    }
+                    KeyCode::Delete if cursor < line.len() => {
+                        line.remove(cursor);
+                    }
+                    KeyCode::Left => cursor = cursor.saturating_sub(1),
+                    KeyCode::Right => cursor = (cursor + 1).min(line.len()),
+                    KeyCode::Home => cursor = 0,
+                    KeyCode::End => cursor = line.len(),
+                    KeyCode::Up if history_index > 0 => {
+                        if history_index == self.history.len() {
+                            draft = line;
+                        }
+                        history_index -= 1;
+                        line = self.history[history_index].chars().collect();
+                        cursor = line.len();
+                    }
+                    KeyCode::Down if history_index < self.history.len() => {
+                        history_index += 1;
+                        line = match self.history.get(history_index) {
+                            Some(entry) => entry.chars().collect(),
+                            None => std::mem::take(&mut draft),
+                        };
+                        cursor = line.len();
+                    }
+                    _ => {}
+                },
+                Event::Paste(text) => {
+                    let mut lines = text.lines();
+                    let first: Vec<char> = lines.next().unwrap_or_default().chars().collect();
+                    let len = first.len();
+                    line.splice(cursor..cursor, first);
+                    cursor += len;
+                    if text.contains('\n') {
+                        self.pending.extend(lines.map(String::from));
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            draw(prompt, &line, cursor)?;
+        }
+
+        print_raw("\n");
+        let line: String = line.into_iter().collect();
+        if !line.trim().is_empty() {
+            self.history.push(line.clone());
+        }
+        Ok(ReadLine::Line(line))
+    }
+}
+
+fn draw(prompt: &str, line: &[char], cursor: usize) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    let text: String = line.iter().collect();
+    queue!(
+        stdout,
+        MoveToColumn(0),
+        Clear(ClearType::CurrentLine),
+        Print(prompt),
+        Print(text),
+        MoveToColumn((prompt.chars().count() + cursor) as u16),
+    )?;
+    stdout.flush()
+}
+
+/// Prints text in raw mode, where a newline does not return the cursor to the start of the line.
+pub(crate) fn print_raw(text: &str) {
+    print!("{}", text.replace('\n', "\r\n"));
+    let _ = std::io::stdout().flush();
+}
+
+/// Moves to a new line if program output left the cursor partway along one.
+pub(crate) fn finish_line() {
+    if position().map(|(column, _)| column > 0).unwrap_or(false) {
+        print_raw("\n");
+    }
+}
+
+/// Reads entries from the terminal and runs them on one machine, so the tape, head and functions carry over.
+pub(crate) fn repl<T: Number + 'static>(args: &Args, trace: &Trace) {
+    print_raw("turing REPL, :help for commands\n");
+    let mut editor = LineEditor::default();
+    // The machine keeps references to every function defined, so the source and commands of each entry it loads live
+    // as long as the REPL.
+    let sources = Arena::new();
+    let programs = Arena::new();
+    let mut machine = machine::<T>(&[], args, Input::Terminal, trace);
+    let mut entry = String::new();
+    loop {
+        let prompt = if entry.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        let line = match editor.read_line(prompt) {
+            Ok(ReadLine::Line(line)) => line,
+            Ok(ReadLine::Quit) | Err(_) => return,
+        };
+
+        if entry.is_empty() && line.trim_start().starts_with(':') {
+            let mut words = line.trim().splitn(2, char::is_whitespace);
+            let command = words.next().unwrap_or_default();
+            let argument = words.next().map(str::trim).unwrap_or_default();
+            match command {
+                ":reset" => machine = crate::machine::<T>(&[], args, Input::Terminal, trace),
+                ":load" if argument.is_empty() => print_raw("usage: :load FILE\n"),
+                ":load" => match std::fs::read_to_string(argument) {
+                    Ok(code) => evaluate(&mut machine, &sources, &programs, code, argument),
+                    Err(error) => print_raw(&format!("error: cannot read `{}`: {}\n", argument, error)),
+                },
+                ":quit" | ":q" => return,
+                command => print_raw(&describe(&machine, command)),
+            }
+            continue;
+        }
+
+        entry.push_str(&line);
+        entry.push('\n');
+        if !is_unfinished(&entry) {
+            evaluate(&mut machine, &sources, &programs, std::mem::take(&mut entry), FILE_NAME);
+        }
+    }
+}
+
+/// What the meta-commands that only report on the machine print, or how to get help for any other command.
+fn describe<T: Number>(machine: &Machine<T>, command: &str) -> String {
+    match command {
+        ":tape" => format!("{}\n", machine.snapshot()),
+        ":head" => format!("{}\n", machine.head()),
+        ":funcs" => machine.functions().enumerate().map(|(index, name)| format!("{}: {}\n", index, name)).collect(),
+        ":help" => format!("{}\n", HELP),
+        command => format!("unknown command `{}`, try :help\n", command),
+    }
+}
+
+/// Whether the entry only fails to parse because a block is still open, so the next line continues it.
+fn is_unfinished(entry: &str) -> bool {
+    match parse(entry) {
+        Ok(_) => false,
+        Err(errors) => errors.iter().all(|error| matches!(error.kind, ParseErrorKind::UnclosedBlock(_))),
+    }
+}
+
+/// Runs an entry on the machine, keeping its source and commands in the arenas only once it parses and resolves.
+fn evaluate<'a, T: Number + 'static>(machine: &mut Machine<'a, T>, sources: &'a Arena<String>, programs: &'a Arena<Vec<SpannedCommand<'a>>>, code: String, file_name: &str) {
+    let known: Vec<&str> = machine.functions().collect();
+    if let Err(errors) = parse(&code).and_then(|commands| resolve(&commands, &known)) {
+        let source_map = SourceMap::new(&code);
+        for error in &errors {
+            print_raw(&Diagnostic::from(error).render(&source_map, file_name));
+        }
+        return;
+    }
+
+    let code = sources.alloc(code);
+    let source_map = SourceMap::new(code);
+    let commands = programs.alloc(parse(code).expect("the entry parsed before"));
+    machine.load(commands);
+    let result = machine.run();
+    finish_line();
+    match result {
+        Ok(()) => {}
+        Err(error) if error.kind == RuntimeErrorKind::Interrupted => print_raw("interrupted\n"),
+        Err(error) => print_raw(&runtime_diagnostic(&error, &source_map, file_name).render(&source_map, file_name)),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use turing_core::io::MemoryIo;
+    use turing_core::Config;
+
+    #[test]
+    fn test_is_unfinished() {
+        assert!(is_unfinished("fun greet\n"));
+        assert!(is_unfinished("3 while -\nif 1\n"));
+        assert!(!is_unfinished("fun greet \"hi\" end\n"));
+        assert!(!is_unfinished("\n"));
+        // Any other error ends the entry, even with a block still open.
+        assert!(!is_unfinished("while \"open\n"));
+        assert!(!is_unfinished("end\n"));
+        assert!(!is_unfinished("fun f ~\n"));
+    }
+
+    #[test]
+    fn test_describe() {
+        let commands = parse("fun one 1 end fun two 2 end 4 5 <").unwrap();
+        let mut machine = Machine::<i64>::new(&commands, Config::default()).with_io(Rc::new(RefCell::new(MemoryIo::new(""))));
+        machine.run().unwrap();
+        assert_eq!(describe(&machine, ":head"), "1\n");
+        assert_eq!(describe(&machine, ":tape"), format!("{}\n", machine.snapshot()));
+        assert_eq!(describe(&machine, ":funcs"), "0: one\n1: two\n");
+        assert_eq!(describe(&machine, ":help"), format!("{}\n", HELP));
+        assert_eq!(describe(&machine, ":fly"), "unknown command `:fly`, try :help\n");
+    }
+}
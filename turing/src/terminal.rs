@@ -0,0 +1,64 @@
+use std::fmt::Display;
+use std::io::{ErrorKind, Write};
+use std::time::Duration;
+use crossterm::event::{poll, read, Event, KeyCode, KeyModifiers};
+use turing_core::io::Io;
+
+
+/// Reads key presses from a terminal in raw mode and stops the program on Ctrl-C.
+#[derive(Debug, Default)]
+pub struct TerminalIo;
+
+impl Io for TerminalIo {
+    fn read_char(&mut self) -> std::io::Result<Option<char>> {
+        loop {
+            if let Event::Key(event) = read()? {
+                match event.code {
+                    KeyCode::Enter => return Ok(Some('\n')),
+                    KeyCode::Char('c') if event.modifiers == KeyModifiers::CONTROL => {
+                        return Err(ErrorKind::Interrupted.into());
+                    }
+                    KeyCode::Char(c) if event.modifiers == KeyModifiers::SHIFT => {
+                        return Ok(c.to_uppercase().next());
+                    }
+                    KeyCode::Char(c) => return Ok(Some(c)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn write_number(&mut self, number: &dyn Display) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout();
+        write!(stdout, "{}", number)?;
+        stdout.flush()
+    }
+
+    fn write_char(&mut self, c: char) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout();
+        // Raw mode does not return the cursor to the start of the line.
+        if c == '\n' {
+            write!(stdout, "\r\n")?;
+        } else {
+            write!(stdout, "{}", c)?;
+        }
+        stdout.flush()
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stdout().flush()
+    }
+
+    fn interrupted(&mut self) -> bool {
+        while poll(Duration::ZERO).unwrap_or(false) {
+            match read() {
+                Ok(Event::Key(event)) if event.code == KeyCode::Char('c') && event.modifiers == KeyModifiers::CONTROL => {
+                    return true;
+                }
+                Ok(_) => {}
+                Err(_) => return false,
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,274 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Print, Stylize};
+use crossterm::terminal::{size, Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use turing_core::io::Io;
+use turing_core::{Machine, RuntimeErrorKind, Status};
+use turing_definitions::ast::{Command, SpannedCommand};
+use turing_definitions::diagnostics::SourceMap;
+use turing_definitions::Number;
+
+use crate::debugger::highlight_line;
+use crate::terminal::TerminalIo;
+use crate::{machine, Args, Input, Trace};
+
+const INITIAL_DELAY: Duration = Duration::from_millis(200);
+const MIN_DELAY: Duration = Duration::from_millis(1);
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+static KEYS: &str = "space pause/resume  s step  + faster  - slower  q quit";
+
+/// Keeps the program's output for the visualizer to draw, and reads `?` from the keyboard.
+#[derive(Debug, Default)]
+struct VisualIo {
+    output: String,
+}
+
+impl Io for VisualIo {
+    fn read_char(&mut self) -> std::io::Result<Option<char>> {
+        TerminalIo.read_char()
+    }
+
+    fn write_number(&mut self, number: &dyn Display) -> std::io::Result<()> {
+        self.output.push_str(&number.to_string());
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> std::io::Result<()> {
+        self.output.push(c);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum State {
+    Running,
+    Paused,
+    /// The program halted, or stopped with the message.
+    Finished(Option<String>),
+}
+
+struct Visualizer<'a, T: Number> {
+    machine: Machine<'a, T>,
+    io: Rc<RefCell<VisualIo>>,
+    source_map: SourceMap<'a>,
+    file_name: &'a str,
+    state: State,
+    delay: Duration,
+}
+
+/// Animates a program in the terminal, showing the tape around the head and the command being run.
+pub(crate) fn visualize<T: Number + 'static>(commands: &[SpannedCommand], code: &str, file_name: &str, args: &Args, trace: &Trace) {
+    let io = Rc::new(RefCell::new(VisualIo::default()));
+    let mut visualizer = Visualizer {
+        machine: machine::<T>(commands, args, Input::Terminal, trace).with_io(io.clone()),
+        io,
+        source_map: SourceMap::new(code),
+        file_name,
+        state: State::Running,
+        delay: INITIAL_DELAY,
+    };
+    let mut stdout = std::io::stdout();
+    let _ = execute!(stdout, EnterAlternateScreen, DisableLineWrap, Hide);
+    let result = visualizer.run();
+    let _ = execute!(stdout, Show, EnableLineWrap, LeaveAlternateScreen);
+    if let Err(error) = result {
+        crate::repl::print_raw(&format!("error: {}\n", error));
+    }
+}
+
+impl<'a, T: Number> Visualizer<'a, T> {
+    fn run(&mut self) -> std::io::Result<()> {
+        let mut next_step = Instant::now();
+        loop {
+            self.draw()?;
+            let event = if self.state == State::Running {
+                if !poll(next_step.saturating_duration_since(Instant::now()))? {
+                    self.step();
+                    next_step = Instant::now() + self.delay;
+                    continue;
+                }
+                read()?
+            } else {
+                read()?
+            };
+            let Event::Key(key) = event else {
+                continue;
+            };
+            if !self.handle_key(key) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns `false` when the key quits the visualizer.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => return false,
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char(' ') => {
+                self.state = match self.state {
+                    State::Running => State::Paused,
+                    State::Paused => State::Running,
+                    ref finished => finished.clone(),
+                };
+            }
+            KeyCode::Char('s') | KeyCode::Right if self.state == State::Paused => self.step(),
+            KeyCode::Char('+') | KeyCode::Up => self.delay = (self.delay / 2).max(MIN_DELAY),
+            KeyCode::Char('-') | KeyCode::Down => self.delay = (self.delay * 2).min(MAX_DELAY),
+            _ => {}
+        }
+        true
+    }
+
+    fn step(&mut self) {
+        match self.machine.step() {
+            Ok(Status::Running) => {}
+            Ok(Status::Halted) => self.state = State::Finished(None),
+            Err(error) if error.kind == RuntimeErrorKind::Interrupted => self.state = State::Finished(Some(String::from("interrupted"))),
+            Err(error) => {
+                let location = self.source_map.location(error.start);
+                let message = format!("error: {} at {}:{}:{}", error, self.file_name, location.line, location.column);
+                self.state = State::Finished(Some(message));
+            }
+        }
+    }
+
+    fn draw(&self) -> std::io::Result<()> {
+        let (width, height) = size()?;
+        let mut stdout = std::io::stdout();
+        queue!(stdout, Clear(ClearType::All))?;
+
+        let state = match &self.state {
+            State::Running => "running",
+            State::Paused => "paused",
+            State::Finished(_) => "finished",
+        };
+        let status = format!(
+            "{}  step {}  head {}  {}  delay {}ms",
+            self.file_name, self.machine.steps(), self.machine.head(), state, self.delay.as_millis(),
+        );
+        queue!(stdout, MoveTo(0, 0), Print(status.bold()))?;
+
+        let (indices, cells, marker) = self.tape_rows(width as usize);
+        queue!(stdout, MoveTo(0, 2), Print(indices.dark_grey()), MoveTo(0, 3), Print(cells), MoveTo(0, 4), Print(marker))?;
+
+        let line = match (&self.state, self.machine.current()) {
+            (State::Finished(Some(message)), _) => message.clone().red().to_string(),
+            (State::Finished(None), _) => String::from("program finished"),
+            (_, Some(command)) => {
+                let location = self.source_map.location(command.start);
+                let waiting = if command.command == Command::ReadKey { "  (press a key for ?)" } else { "" };
+                format!("{:>4} | {}{}", location.line, highlight_line(&self.source_map, command), waiting)
+            }
+            (_, None) => String::new(),
+        };
+        queue!(stdout, MoveTo(0, 6), Print(line))?;
+
+        // The output fills the rows between the source line and the key help.
+        queue!(stdout, MoveTo(0, 8), Print("output".bold()))?;
+        let rows = (height as usize).saturating_sub(11);
+        let io = self.io.borrow();
+        let lines: Vec<&str> = io.output.split('\n').collect();
+        for (row, line) in lines[lines.len().saturating_sub(rows)..].iter().enumerate() {
+            queue!(stdout, MoveTo(0, 9 + row as u16), Print(line))?;
+        }
+        queue!(stdout, MoveTo(0, height.saturating_sub(1)), Print(KEYS.dark_grey()))?;
+        stdout.flush()
+    }
+
+    /// The cell indices, the cells and a marker under the head, for as many cells around the head as fit in `width`.
+    fn tape_rows(&self, width: usize) -> (String, String, String) {
+        let tape = self.machine.tape();
+        let head = self.machine.head();
+        let cell = |index: isize| tape.in_bounds(index).then(|| tape.get(index).to_string());
+        // Cells are as wide as the widest one near the head, so the window only changes width for large values.
+        let cell_width = (head.saturating_sub(4)..=head.saturating_add(4))
+            .filter_map(|index| cell(index).map(|value| value.len().max(index.to_string().len())))
+            .max()
+            .unwrap_or(1)
+            .max(3) + 1;
+        let count = (width / cell_width).max(1) as isize;
+        let start = head.saturating_sub((count - 1) / 2).min(isize::MAX - count);
+
+        let mut indices = String::new();
+        let mut cells = String::new();
+        let mut marker = String::new();
+        for index in start..start + count {
+            indices.push_str(&format!("{:>width$}", index, width = cell_width));
+            match cell(index) {
+                Some(value) if index == head => {
+                    cells.push_str(&format!(" {}", format!("{:>width$}", value, width = cell_width - 1).reverse()));
+                }
+                Some(value) => cells.push_str(&format!("{:>width$}", value, width = cell_width)),
+                None => cells.push_str(&format!("{:>width$}", "·", width = cell_width)),
+            }
+            marker.push_str(&format!("{:>width$}", if index == head { "^" } else { "" }, width = cell_width));
+        }
+        (indices, cells, marker)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use turing_core::Config;
+    use turing_definitions::parser::parse;
+
+    fn visualizer<'a>(commands: &'a [SpannedCommand<'a>], code: &'a str) -> Visualizer<'a, i64> {
+        let io = Rc::new(RefCell::new(VisualIo::default()));
+        Visualizer {
+            machine: Machine::new(commands, Config::default()).with_io(io.clone()),
+            io,
+            source_map: SourceMap::new(code),
+            file_name: "test.txt",
+            state: State::Running,
+            delay: INITIAL_DELAY,
+        }
+    }
+
+    #[test]
+    fn test_tape_rows() {
+        let code = "5 6 7 <";
+        let commands = parse(code).unwrap();
+        let mut visualizer = visualizer(&commands, code);
+        let (indices, cells, marker) = visualizer.tape_rows(20);
+        assert_eq!(indices, "  -2  -1   0   1   2");
+        assert_eq!(cells, format!("   ·   · {}   ·   ·", "  0".reverse()));
+        assert_eq!(marker, "           ^        ");
+
+        visualizer.machine.run().unwrap();
+        let (indices, cells, marker) = visualizer.tape_rows(22);
+        assert_eq!(indices, "   0   1   2   3   4");
+        assert_eq!(cells, format!("   0   5 {}   7   ·", "  6".reverse()));
+        assert_eq!(marker, "           ^        ");
+    }
+
+    #[test]
+    fn test_tape_rows_widen_for_large_values() {
+        let code = "123456 7 <";
+        let commands = parse(code).unwrap();
+        let mut visualizer = visualizer(&commands, code);
+        visualizer.machine.run().unwrap();
+        let (indices, cells, marker) = visualizer.tape_rows(20);
+        assert_eq!(indices, "      1      2");
+        assert_eq!(cells, format!(" {}      7", "123456".reverse()));
+        assert_eq!(marker, "      ^       ");
+
+        // At least the head's cell is shown, however narrow the terminal.
+        let (indices, _, marker) = visualizer.tape_rows(3);
+        assert_eq!(indices, "      1");
+        assert_eq!(marker, "      ^");
+    }
+}
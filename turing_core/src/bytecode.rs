@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use turing_definitions::ast::{Command, SpannedCommand};
+use turing_definitions::{Direction, Number, Operation, OverflowMode};
+use crate::optimize;
+
+/// One instruction of a compiled [`Program`].
+///
+/// Jump targets are indices into [`Program::instructions`]. Every instruction counts as one step, as the command
+/// it came from does in a [`Machine`](crate::Machine), except for [`Jump`](Instruction::Jump),
+/// [`Return`](Instruction::Return) and [`Halt`](Instruction::Halt), which only stand for the end of a block, and
+/// [`Repeat`](Instruction::Repeat), which counts as a step when it jumps back. Instructions made by
+/// [`compile_optimized`] stand for several commands, or a whole loop, in one step. They are followed by the commands
+/// as written, which they skip, unless running them all at once would fail, so that errors point at the command that
+/// failed and leave the tape as it would have been.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction<T> {
+    /// Moves the head right and writes the value, for a number.
+    MoveSet(T),
+    /// Moves the head right and writes each value, for a string.
+    MoveSetEach(Box<[T]>),
+    /// Writes the value without moving, for a number in brackets.
+    Set(T),
+    /// Writes each value and moves the head right after it, for a string in brackets.
+    SetEach(Box<[T]>),
+    MoveLeft(u64),
+    MoveRight(u64),
+    /// Moves the head by the value of the current cell.
+    ReadMove(Direction),
+    Increment,
+    Decrement,
+    /// Moves the head by the total of a run of moves in one direction and jumps to the target past the run. It does
+    /// nothing if the tape cannot reach the cell.
+    MoveRun(Direction, u64, usize),
+    /// Adds the value to the current cell and jumps to the target past the run of `+` and `-` it stands for. It does
+    /// nothing if the tape has no room to make a zero cell nonzero.
+    Add(T, usize),
+    /// Subtracts the value from the current cell, like [`Add`](Instruction::Add).
+    Subtract(T, usize),
+    /// Adds the current cell times each factor to the cell at each offset from the head and clears the current cell,
+    /// for a loop like `while - > + < end`. It does nothing if the tape cannot reach every offset or has no room for
+    /// the new values. The offsets the loop moves furthest to come first, in the order the loop reaches them, so the
+    /// tape only grows where the loop would have grown it.
+    Transfer(Box<[(isize, T)]>),
+    /// Writes cells as characters, moving right until a cell is zero, for `while , > end`. If writing or moving fails,
+    /// it jumps to the target, the loop's `,`, or the `>` after it, to try again as written.
+    Print(usize),
+    /// Applies the operation to the cell `offset` cells away in `direction`, with the current cell as the operand.
+    Operate(Direction, usize, Operation),
+    /// Jumps to the target if the current cell is zero, for entering an `if` or `while`.
+    Branch(usize),
+    /// Jumps to the target if the current cell is not zero, for the end of a `while` or `loop` body.
+    Repeat(usize),
+    /// Jumps to the target unconditionally, from the end of an `if` body past its `else` body.
+    Jump(usize),
+    /// Does nothing, for entering a `loop` and for comments.
+    Nop,
+    /// Points a name at a function when its `fun` is reached, giving the function the next index if it has none.
+    Define { name: usize, function: usize },
+    /// Calls the function the name points at.
+    Call(usize),
+    /// Writes the index of the function the name points at.
+    GetFunction(usize),
+    /// Calls the function whose index is in the current cell.
+    CallIndirect,
+    Return,
+    OutputNumber,
+    OutputChar,
+    ReadKey,
+    Halt,
+}
+
+/// A function body in a [`Program`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    /// The index of the function's name in [`Program::names`].
+    pub name: usize,
+    /// The index of the function's first instruction.
+    pub entry: usize,
+}
+
+/// A program lowered to a flat list of instructions, run by a [`Vm`](crate::Vm).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program<T> {
+    pub instructions: Vec<Instruction<T>>,
+    /// The source span of each instruction, for errors.
+    pub spans: Vec<(usize, usize)>,
+    /// The function names used by the program, indexed by [`Instruction::Call`] and friends.
+    pub names: Vec<String>,
+    /// Every `fun` in the program, indexed by [`Instruction::Define`].
+    pub functions: Vec<Function>,
+    /// The functions defined outside of any block, in order, which are defined before the program starts as
+    /// [`Machine::load`](crate::Machine::load) defines them.
+    pub hoisted: Vec<usize>,
+}
+
+/// Lowers a program's commands into bytecode, with the top-level commands first, followed by each function body.
+pub fn compile<T: Number>(commands: &[SpannedCommand]) -> Program<T> {
+    lower(commands, None)
+}
+
+/// Lowers a program like [`compile`], folding runs of commands and common loops into single instructions.
+///
+/// Run with cells of type `T` under `mode`, the program gives the same output, leaves the same tape and fails with the
+/// same errors, but takes fewer steps, so it should not be run with a step limit.
+pub fn compile_optimized<T: Number>(commands: &[SpannedCommand], mode: OverflowMode) -> Program<T> {
+    lower(commands, Some(mode))
+}
+
+fn lower<T: Number>(commands: &[SpannedCommand], mode: Option<OverflowMode>) -> Program<T> {
+    let mut compiler = Compiler {
+        program: Program {
+            instructions: Vec::new(),
+            spans: Vec::new(),
+            names: Vec::new(),
+            functions: Vec::new(),
+            hoisted: Vec::new(),
+        },
+        name_ids: HashMap::new(),
+        bodies: Vec::new(),
+        mode,
+    };
+    let mut rest = commands;
+    while let Some(command) = rest.first() {
+        if let Command::FunctionDefinition(..) = command.command {
+            let function = compiler.program.functions.len();
+            compiler.program.hoisted.push(function);
+        }
+        rest = &rest[compiler.next(rest)..];
+    }
+    let end = commands.last().map_or(0, |command| command.end);
+    compiler.emit(Instruction::Halt, (end, end));
+
+    // A body can define more functions, so this runs until no bodies are left.
+    let mut function = 0;
+    while function < compiler.bodies.len() {
+        let (body, span) = compiler.bodies[function];
+        compiler.program.functions[function].entry = compiler.program.instructions.len();
+        compiler.block(body);
+        compiler.emit(Instruction::Return, span);
+        function += 1;
+    }
+    compiler.program
+}
+
+struct Compiler<'c, 'a, T> {
+    program: Program<T>,
+    name_ids: HashMap<&'a str, usize>,
+    /// The body and `fun` span of each function, in the order of `program.functions`.
+    bodies: Vec<(&'c [SpannedCommand<'a>], (usize, usize))>,
+    /// The overflow mode to optimize for, or `None` to lower every command as written.
+    mode: Option<OverflowMode>,
+}
+
+impl<'c, 'a, T: Number> Compiler<'c, 'a, T> {
+    fn emit(&mut self, instruction: Instruction<T>, span: (usize, usize)) -> usize {
+        self.program.instructions.push(instruction);
+        self.program.spans.push(span);
+        self.program.instructions.len() - 1
+    }
+
+    /// Points a jump emitted earlier at the next instruction.
+    fn patch(&mut self, jump: usize) {
+        let target = self.program.instructions.len();
+        match &mut self.program.instructions[jump] {
+            Instruction::Branch(to) | Instruction::Jump(to) => *to = target,
+            Instruction::MoveRun(_, _, to) | Instruction::Add(_, to) | Instruction::Subtract(_, to) => *to = target,
+            instruction => unreachable!("{:?} is not a forward jump", instruction),
+        }
+    }
+
+    fn name(&mut self, name: &'a str) -> usize {
+        *self.name_ids.entry(name).or_insert_with(|| {
+            self.program.names.push(name.to_string());
+            self.program.names.len() - 1
+        })
+    }
+
+    fn block(&mut self, commands: &'c [SpannedCommand<'a>]) {
+        let mut rest = commands;
+        while !rest.is_empty() {
+            rest = &rest[self.next(rest)..];
+        }
+    }
+
+    /// Lowers the first command, or the run of commands it starts, returning how many commands were lowered.
+    fn next(&mut self, commands: &'c [SpannedCommand<'a>]) -> usize {
+        if let Some((instruction, len)) = self.mode.and_then(|mode| optimize::fold_run(commands, mode)) {
+            let fold = self.emit(instruction, (commands[0].start, commands[len - 1].end));
+            for command in &commands[..len] {
+                self.command(command);
+            }
+            self.patch(fold);
+            return len;
+        }
+        self.command(&commands[0]);
+        1
+    }
+
+    fn command(&mut self, command: &'c SpannedCommand<'a>) {
+        let span = (command.start, command.end);
+        let instruction = match &command.command {
+            Command::AddInteger(i) => Instruction::MoveSet(T::from(*i)),
+            Command::AddFloat(f) => Instruction::MoveSet(T::from_f64(*f)),
+            Command::AddString(s) => Instruction::MoveSetEach(s.chars().map(T::from_char).collect()),
+            Command::WriteInteger(i) => Instruction::Set(T::from(*i)),
+            Command::WriteFloat(f) => Instruction::Set(T::from_f64(*f)),
+            Command::WriteString(s) => Instruction::SetEach(s.chars().map(T::from_char).collect()),
+            Command::MoveLeft => Instruction::MoveLeft(1),
+            Command::MoveRight => Instruction::MoveRight(1),
+            Command::ReadMoveLeft => Instruction::ReadMove(Direction::Left),
+            Command::ReadMoveRight => Instruction::ReadMove(Direction::Right),
+            Command::MoveNLeft(n) => Instruction::MoveLeft(*n),
+            Command::MoveNRight(n) => Instruction::MoveRight(*n),
+            Command::Increment => Instruction::Increment,
+            Command::Decrement => Instruction::Decrement,
+            Command::LeftAdd(offset) => operate(Direction::Left, *offset, Operation::Add),
+            Command::RightAdd(offset) => operate(Direction::Right, *offset, Operation::Add),
+            Command::LeftSubtract(offset) => operate(Direction::Left, *offset, Operation::Subtract),
+            Command::RightSubtract(offset) => operate(Direction::Right, *offset, Operation::Subtract),
+            Command::LeftMultiply(offset) => operate(Direction::Left, *offset, Operation::Multiply),
+            Command::RightMultiply(offset) => operate(Direction::Right, *offset, Operation::Multiply),
+            Command::LeftDivide(offset) => operate(Direction::Left, *offset, Operation::Divide),
+            Command::RightDivide(offset) => operate(Direction::Right, *offset, Operation::Divide),
+            Command::LeftModulo(offset) => operate(Direction::Left, *offset, Operation::Modulo),
+            Command::RightModulo(offset) => operate(Direction::Right, *offset, Operation::Modulo),
+            Command::If(if_commands, else_commands) => {
+                let branch = self.emit(Instruction::Branch(0), span);
+                self.block(if_commands);
+                match else_commands {
+                    Some(else_commands) => {
+                        let jump = self.emit(Instruction::Jump(0), span);
+                        self.patch(branch);
+                        self.block(else_commands);
+                        self.patch(jump);
+                    }
+                    None => self.patch(branch),
+                }
+                return;
+            }
+            Command::While(commands) => {
+                if let Some(mode) = self.mode {
+                    if optimize::clears::<T>(commands, mode) {
+                        self.emit(Instruction::Set(T::from(0)), span);
+                        return;
+                    }
+                    if optimize::prints(commands) {
+                        // The loop's body starts after the `Print` and the `Branch` into the loop.
+                        let body = self.program.instructions.len() + 2;
+                        self.emit(Instruction::Print(body), span);
+                    } else if let Some(transfer) = optimize::transfer(commands, mode) {
+                        self.emit(transfer, span);
+                    }
+                }
+                let branch = self.emit(Instruction::Branch(0), span);
+                let body = self.program.instructions.len();
+                self.block(commands);
+                self.emit(Instruction::Repeat(body), span);
+                self.patch(branch);
+                return;
+            }
+            Command::Loop(commands) => {
+                self.emit(Instruction::Nop, span);
+                let body = self.program.instructions.len();
+                self.block(commands);
+                self.emit(Instruction::Repeat(body), span);
+                return;
+            }
+            Command::FunctionDefinition(name, commands) => {
+                let name = self.name(name);
+                let function = self.program.functions.len();
+                self.program.functions.push(Function { name, entry: 0 });
+                self.bodies.push((commands, span));
+                Instruction::Define { name, function }
+            }
+            Command::FunctionCall(name) => Instruction::Call(self.name(name)),
+            Command::GetFunction(name) => Instruction::GetFunction(self.name(name)),
+            Command::CallFunction => Instruction::CallIndirect,
+            Command::OutputNumber => Instruction::OutputNumber,
+            Command::OutputChar => Instruction::OutputChar,
+            Command::ReadKey => Instruction::ReadKey,
+            Command::Comment => Instruction::Nop,
+        };
+        self.emit(instruction, span);
+    }
+}
+
+fn operate<T>(direction: Direction, offset: Option<usize>, operation: Operation) -> Instruction<T> {
+    Instruction::Operate(direction, offset.unwrap_or(1), operation)
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+use turing_definitions::{Number, Tape, TapeError};
+
+/// Number of cells on either side of the head kept in a [`TapeSnapshot`].
+const SNAPSHOT_RADIUS: usize = 8;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RuntimeErrorKind {
+    Overflow,
+    DivisionByZero,
+    TapeUnderflow,
+    UnknownFunction(String),
+    /// `call` found a cell that is not the index of a defined function.
+    InvalidFunctionIndex(String),
+    /// More functions were nested than [`Config::max_call_depth`](crate::Config::max_call_depth).
+    StackOverflow(usize),
+    TapeLimitExceeded(usize),
+    /// There was not enough memory for the cells the tape needed.
+    OutOfMemory,
+    /// The head would move past the last cell an `isize` can index.
+    TapeOutOfRange,
+    /// The program executed its maximum number of steps.
+    StepLimitExceeded(u64),
+    /// The program ran past its deadline.
+    Timeout(Duration),
+    /// The program was stopped by its [`Io`](crate::io::Io), such as by Ctrl-C in a terminal.
+    Interrupted,
+    Io(String),
+}
+
+impl From<std::io::Error> for RuntimeErrorKind {
+    fn from(error: std::io::Error) -> RuntimeErrorKind {
+        match error.kind() {
+            std::io::ErrorKind::Interrupted => RuntimeErrorKind::Interrupted,
+            _ => RuntimeErrorKind::Io(error.to_string()),
+        }
+    }
+}
+
+impl RuntimeErrorKind {
+    /// Whether the program was stopped by a step or time limit rather than failing on its own.
+    pub fn is_limit(&self) -> bool {
+        matches!(self, RuntimeErrorKind::StepLimitExceeded(_) | RuntimeErrorKind::Timeout(_))
+    }
+}
+
+impl From<TapeError> for RuntimeErrorKind {
+    fn from(error: TapeError) -> RuntimeErrorKind {
+        match error {
+            TapeError::Overflow => RuntimeErrorKind::Overflow,
+            TapeError::DivisionByZero => RuntimeErrorKind::DivisionByZero,
+            TapeError::Underflow => RuntimeErrorKind::TapeUnderflow,
+            TapeError::LimitExceeded(max_cells) => RuntimeErrorKind::TapeLimitExceeded(max_cells),
+            TapeError::OutOfMemory => RuntimeErrorKind::OutOfMemory,
+            TapeError::OutOfRange => RuntimeErrorKind::TapeOutOfRange,
+        }
+    }
+}
+
+/// The cells around the head at the moment an error occurred.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TapeSnapshot<T> {
+    /// Index of the first cell in `cells`.
+    pub start: isize,
+    pub head: isize,
+    pub cells: Vec<T>,
+}
+
+impl<T: Number> TapeSnapshot<T> {
+    /// Copies the cells within a few cells of `head`.
+    pub fn new(tape: &dyn Tape<T>, head: isize) -> TapeSnapshot<T> {
+        let radius = SNAPSHOT_RADIUS as isize;
+        let indices = (head.saturating_sub(radius)..=head.saturating_add(radius))
+            .filter(|index| tape.in_bounds(*index));
+        let start = indices.clone().next().unwrap_or(head);
+        let cells = indices.map(|index| tape.get(index)).collect();
+        TapeSnapshot {
+            start,
+            head,
+            cells,
+        }
+    }
+}
+
+impl<T: Number> std::fmt::Display for TapeSnapshot<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cells {}..{}:", self.start, self.start as i128 + self.cells.len() as i128)?;
+        for (i, cell) in self.cells.iter().enumerate() {
+            if self.start + i as isize == self.head {
+                write!(f, " [{}]", cell)?;
+            } else {
+                write!(f, " {}", cell)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function being run, with the span of the command that called it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StackFrame {
+    pub function: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuntimeError<T> {
+    pub kind: RuntimeErrorKind,
+    pub start: usize,
+    pub end: usize,
+    pub tape: TapeSnapshot<T>,
+    /// The functions being run when the error occurred, innermost first.
+    pub stack: Vec<StackFrame>,
+}
+
+impl<T> std::fmt::Display for RuntimeError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            RuntimeErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            RuntimeErrorKind::DivisionByZero => write!(f, "division by zero"),
+            RuntimeErrorKind::TapeUnderflow => write!(f, "cell is before the start of the tape"),
+            RuntimeErrorKind::UnknownFunction(name) => write!(f, "unknown function `{}`", name),
+            RuntimeErrorKind::InvalidFunctionIndex(value) => write!(f, "no function has index {}", value),
+            RuntimeErrorKind::StackOverflow(max_depth) => write!(f, "stack overflow, more than {} nested function calls", max_depth),
+            RuntimeErrorKind::TapeLimitExceeded(max_cells) => write!(f, "tape limit exceeded, more than {} cells", max_cells),
+            RuntimeErrorKind::OutOfMemory => write!(f, "out of memory for the tape"),
+            RuntimeErrorKind::TapeOutOfRange => write!(f, "cell is past the end of the tape"),
+            RuntimeErrorKind::StepLimitExceeded(max_steps) => write!(f, "step limit exceeded, more than {} steps", max_steps),
+            RuntimeErrorKind::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+            RuntimeErrorKind::Interrupted => write!(f, "interrupted"),
+            RuntimeErrorKind::Io(error) => write!(f, "i/o error: {}", error),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for RuntimeError<T> {}
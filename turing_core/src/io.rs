@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, BufWriter, Stdin, Stdout, Write};
+
+/// Where the interpreter reads input from and writes output to.
+pub trait Io {
+    /// Reads one character, returning `None` at the end of the input.
+    ///
+    /// An error of kind [`std::io::ErrorKind::Interrupted`] stops the program.
+    fn read_char(&mut self) -> std::io::Result<Option<char>>;
+    fn write_number(&mut self, number: &dyn Display) -> std::io::Result<()>;
+    fn write_char(&mut self, c: char) -> std::io::Result<()>;
+    fn flush(&mut self) -> std::io::Result<()>;
+
+    /// Checked before every step of a [`Machine`](crate::Machine) and every 1024 steps of a [`Vm`](crate::Vm);
+    /// returning `true` stops the program.
+    fn interrupted(&mut self) -> bool {
+        false
+    }
+}
+
+
+/// Reads UTF-8 input from a reader and writes output to a writer, such as stdin and stdout.
+#[derive(Debug)]
+pub struct StreamIo<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: BufRead, W: Write> StreamIo<R, W> {
+    pub fn new(reader: R, writer: W) -> StreamIo<R, W> {
+        StreamIo {
+            reader,
+            writer,
+        }
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl StreamIo<BufReader<Stdin>, BufWriter<Stdout>> {
+    /// Reads stdin and writes buffered stdout, only locking either while in use.
+    pub fn stdio() -> Self {
+        Self::new(BufReader::new(std::io::stdin()), BufWriter::new(std::io::stdout()))
+    }
+}
+
+impl<R: BufRead, W: Write> Io for StreamIo<R, W> {
+    fn read_char(&mut self) -> std::io::Result<Option<char>> {
+        let mut bytes = [0; 4];
+        if self.reader.read(&mut bytes[..1])? == 0 {
+            return Ok(None);
+        }
+        let len = match bytes[0] {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+        };
+        // A sequence cut short by another character or the end of the input is replaced, leaving that character unread.
+        for byte in &mut bytes[1..len] {
+            match self.reader.fill_buf()?.first() {
+                Some(&continuation) if continuation & 0b1100_0000 == 0b1000_0000 => {
+                    *byte = continuation;
+                    self.reader.consume(1);
+                }
+                _ => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+            }
+        }
+        let c = std::str::from_utf8(&bytes[..len])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        Ok(Some(c))
+    }
+
+    fn write_number(&mut self, number: &dyn Display) -> std::io::Result<()> {
+        write!(self.writer, "{}", number)
+    }
+
+    fn write_char(&mut self, c: char) -> std::io::Result<()> {
+        write!(self.writer, "{}", c)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+
+/// Reads input from and collects output in memory, for tests and embedding.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MemoryIo {
+    input: VecDeque<char>,
+    output: String,
+}
+
+impl MemoryIo {
+    pub fn new(input: &str) -> MemoryIo {
+        MemoryIo {
+            input: input.chars().collect(),
+            output: String::new(),
+        }
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+}
+
+impl Io for MemoryIo {
+    fn read_char(&mut self) -> std::io::Result<Option<char>> {
+        Ok(self.input.pop_front())
+    }
+
+    fn write_number(&mut self, number: &dyn Display) -> std::io::Result<()> {
+        self.output.push_str(&number.to_string());
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> std::io::Result<()> {
+        self.output.push(c);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stream_io_decodes_utf8() {
+        let mut io = StreamIo::new("aλ€😀".as_bytes(), Vec::new());
+        assert_eq!(io.read_char().unwrap(), Some('a'));
+        assert_eq!(io.read_char().unwrap(), Some('λ'));
+        assert_eq!(io.read_char().unwrap(), Some('€'));
+        assert_eq!(io.read_char().unwrap(), Some('😀'));
+        assert_eq!(io.read_char().unwrap(), None);
+    }
+
+    #[test]
+    fn test_stream_io_invalid_utf8() {
+        let mut io = StreamIo::new(&[0xFF, 0xC3, 0x28, b'x'][..], Vec::new());
+        assert_eq!(io.read_char().unwrap(), Some(char::REPLACEMENT_CHARACTER));
+        assert_eq!(io.read_char().unwrap(), Some(char::REPLACEMENT_CHARACTER));
+        assert_eq!(io.read_char().unwrap(), Some('('));
+        assert_eq!(io.read_char().unwrap(), Some('x'));
+        assert_eq!(io.read_char().unwrap(), None);
+    }
+
+    #[test]
+    fn test_stream_io_truncated_utf8() {
+        let mut io = StreamIo::new(&[b'a', 0xE2, 0x82][..], Vec::new());
+        assert_eq!(io.read_char().unwrap(), Some('a'));
+        assert_eq!(io.read_char().unwrap(), Some(char::REPLACEMENT_CHARACTER));
+        assert_eq!(io.read_char().unwrap(), None);
+    }
+
+    #[test]
+    fn test_stream_io_writes() {
+        let mut io = StreamIo::new(&b""[..], Vec::new());
+        io.write_number(&-42).unwrap();
+        io.write_char('\n').unwrap();
+        io.flush().unwrap();
+        assert_eq!(io.into_inner().1, b"-42\n");
+    }
+
+    #[test]
+    fn test_memory_io() {
+        let mut io = MemoryIo::new("hi");
+        assert_eq!(io.read_char().unwrap(), Some('h'));
+        assert_eq!(io.read_char().unwrap(), Some('i'));
+        assert_eq!(io.read_char().unwrap(), None);
+        io.write_number(&1.5).unwrap();
+        io.write_char('!').unwrap();
+        assert_eq!(io.output(), "1.5!");
+    }
+}
@@ -1,3 +1,14 @@
+pub mod bytecode;
+pub mod error;
+pub mod io;
+pub mod machine;
+mod optimize;
+#[cfg(test)]
+mod testing;
+pub mod trace;
+pub mod vm;
 
-
-
+pub use error::{RuntimeError, RuntimeErrorKind, StackFrame, TapeSnapshot};
+pub use bytecode::{compile, compile_optimized, Program};
+pub use machine::{Config, Machine, Status};
+pub use vm::Vm;
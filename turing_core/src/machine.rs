@@ -0,0 +1,945 @@
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use turing_definitions::ast::{Command, SpannedCommand};
+use turing_definitions::{offset_index, Direction, Number, Operation, OverflowMode, Tape, TapeError};
+use crate::error::{RuntimeError, RuntimeErrorKind, StackFrame, TapeSnapshot};
+use crate::io::{Io, StreamIo};
+use crate::trace::{CellAccess, TraceEvent, Tracer};
+
+/// The default for [`Config::max_call_depth`].
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// Settings that stay fixed while a [`Machine`] runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config<T> {
+    pub overflow_mode: OverflowMode,
+    /// Stops the program with [`RuntimeErrorKind::StepLimitExceeded`] after this many steps.
+    ///
+    /// Every command counts as a step, and so does every repeat of a `while` or `loop` body.
+    pub max_steps: Option<u64>,
+    /// Stops the program with [`RuntimeErrorKind::Timeout`] once this long has passed since it was loaded.
+    ///
+    /// The clock is read between steps, every step for a `Machine` and every 1024 for a [`Vm`](crate::Vm), so a
+    /// program waiting on input can run past it.
+    pub timeout: Option<Duration>,
+    /// Stops the program with [`RuntimeErrorKind::StackOverflow`] when a call would nest more functions than this.
+    pub max_call_depth: usize,
+    /// The value `?` writes once the input has run out.
+    pub eof_value: T,
+}
+
+impl<T: Number> Default for Config<T> {
+    fn default() -> Self {
+        Config {
+            overflow_mode: OverflowMode::default(),
+            max_steps: None,
+            timeout: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            eof_value: T::default(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Status {
+    Running,
+    Halted,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Block {
+    Program,
+    If,
+    While,
+    Loop,
+    Function,
+}
+
+/// A block being executed, with the command that entered it.
+#[derive(Debug, Clone)]
+pub struct Frame<'a> {
+    pub block: Block,
+    /// The `if`, `while`, `loop` or function call that entered the block, `None` for the program itself.
+    pub command: Option<&'a SpannedCommand<'a>>,
+    /// The name of the function, for a function's frame.
+    pub function: Option<&'a str>,
+    commands: &'a [SpannedCommand<'a>],
+    index: usize,
+}
+
+impl<'a> Frame<'a> {
+    /// The index of the next command in the block.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+/// Runs a program one command at a time, keeping nested blocks on an explicit stack of frames.
+pub struct Machine<'a, T: Number> {
+    tape: Rc<RefCell<dyn Tape<T>>>,
+    head: isize,
+    frames: Vec<Frame<'a>>,
+    functions: HashMap<&'a str, usize>,
+    functions_list: Vec<(&'a str, &'a [SpannedCommand<'a>])>,
+    call_depth: usize,
+    steps: u64,
+    deadline: Option<Instant>,
+    /// The command most recently stepped, for errors that happen between commands.
+    last: Option<&'a SpannedCommand<'a>>,
+    io: Rc<RefCell<dyn Io>>,
+    tracer: Option<Rc<RefCell<dyn Tracer<T>>>>,
+    /// Cells read and written by the current step, only recorded while tracing.
+    reads: Vec<CellAccess<T>>,
+    writes: Vec<CellAccess<T>>,
+    config: Config<T>,
+}
+
+impl<'a, T: Number + 'static> Machine<'a, T> {
+    /// Creates a machine with an empty tape that only grows right, reading stdin and writing stdout.
+    pub fn new(program: &'a [SpannedCommand<'a>], config: Config<T>) -> Machine<'a, T> {
+        let mut machine = Machine {
+            tape: Rc::new(RefCell::new(vec![T::default()])),
+            head: 0,
+            frames: Vec::new(),
+            functions: HashMap::new(),
+            functions_list: Vec::new(),
+            call_depth: 0,
+            steps: 0,
+            deadline: None,
+            last: None,
+            io: Rc::new(RefCell::new(StreamIo::stdio())),
+            tracer: None,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            config,
+        };
+        machine.load(program);
+        machine
+    }
+
+    pub fn with_tape(self, tape: Rc<RefCell<dyn Tape<T>>>) -> Machine<'a, T> {
+        Self {
+            tape,
+            head: 0,
+            ..self
+        }
+    }
+
+    pub fn with_io(self, io: Rc<RefCell<dyn Io>>) -> Machine<'a, T> {
+        Self {
+            io,
+            ..self
+        }
+    }
+
+    /// Passes every step to `tracer`, with the cells it read and wrote.
+    pub fn with_tracer(self, tracer: Rc<RefCell<dyn Tracer<T>>>) -> Machine<'a, T> {
+        Self {
+            tracer: Some(tracer),
+            ..self
+        }
+    }
+}
+
+impl<'a, T: Number> Machine<'a, T> {
+    /// Replaces the program being run, keeping the tape, head and functions.
+    ///
+    /// The program's top-level functions are defined straight away, so they can be called before their `fun`.
+    /// The step count and deadline start over.
+    pub fn load(&mut self, program: &'a [SpannedCommand<'a>]) {
+        for command in program {
+            if let Command::FunctionDefinition(name, commands) = &command.command {
+                // A name defined twice starts out as its first definition, until the second `fun` runs.
+                let index = self.function_index(name, commands);
+                self.functions.entry(name).or_insert(index);
+            }
+        }
+        self.frames = vec![Frame {
+            block: Block::Program,
+            command: None,
+            function: None,
+            commands: program,
+            index: 0,
+        }];
+        self.call_depth = 0;
+        self.steps = 0;
+        self.deadline = self.config.timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        self.unwind();
+    }
+
+    /// Runs the program until it halts or fails.
+    pub fn run(&mut self) -> Result<(), RuntimeError<T>> {
+        while self.step()? == Status::Running {}
+        Ok(())
+    }
+
+    /// Executes the next command, or the next repeat of a `while` or `loop` body.
+    pub fn step(&mut self) -> Result<Status, RuntimeError<T>> {
+        let result = self.step_inner();
+        if !matches!(result, Ok(Status::Running)) {
+            let flushed = self.io.borrow_mut().flush()
+                .and(self.tracer.as_ref().map_or(Ok(()), |tracer| tracer.borrow_mut().flush()));
+            if let (Err(error), Some(command)) = (flushed, self.last) {
+                result?;
+                return Err(self.error(error.into(), command));
+            }
+        }
+        result
+    }
+
+    fn step_inner(&mut self) -> Result<Status, RuntimeError<T>> {
+        let Some(command) = self.current() else {
+            return Ok(Status::Halted);
+        };
+        // Checked before the frame moves on, so a stopped machine can carry on from the same command.
+        self.count_step(command)?;
+        let head = self.head;
+        self.reads.clear();
+        self.writes.clear();
+        let frame = self.frames.last_mut().expect("a current command has a frame");
+        if frame.index < frame.commands.len() {
+            frame.index += 1;
+            self.execute(command)?;
+        } else {
+            // `unwind` only leaves a finished frame on top when its body repeats.
+            frame.index = 0;
+        }
+        self.unwind();
+        self.trace(command, head)?;
+        Ok(self.status())
+    }
+
+    fn trace(&mut self, command: &SpannedCommand, head_before: isize) -> Result<(), RuntimeError<T>> {
+        let Some(tracer) = &self.tracer else {
+            return Ok(());
+        };
+        let event = TraceEvent {
+            step: self.steps,
+            start: command.start,
+            end: command.end,
+            kind: command.command.kind(),
+            head_before,
+            head_after: self.head,
+            reads: std::mem::take(&mut self.reads),
+            writes: std::mem::take(&mut self.writes),
+        };
+        let result = tracer.borrow_mut().trace(&event);
+        result.map_err(|error| self.error(error.into(), command))
+    }
+
+    pub fn status(&self) -> Status {
+        if self.frames.is_empty() {
+            Status::Halted
+        } else {
+            Status::Running
+        }
+    }
+
+    /// The command the next [`step`](Machine::step) will execute, or the `while` or `loop` it will repeat.
+    pub fn current(&self) -> Option<&'a SpannedCommand<'a>> {
+        let frame = self.frames.last()?;
+        frame.commands.get(frame.index).or(frame.command)
+    }
+
+    pub fn tape(&self) -> Ref<'_, dyn Tape<T>> {
+        self.tape.borrow()
+    }
+
+    pub fn head(&self) -> isize {
+        self.head
+    }
+
+    /// The number of steps executed since the program was loaded.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The blocks being executed, outermost first.
+    pub fn frames(&self) -> &[Frame<'a>] {
+        &self.frames
+    }
+
+    /// The functions being run, innermost first, with the commands that called them.
+    pub fn call_stack(&self) -> Vec<StackFrame> {
+        self.frames.iter().rev()
+            .filter_map(|frame| {
+                let (function, command) = frame.function.zip(frame.command)?;
+                Some(StackFrame {
+                    function: function.to_string(),
+                    start: command.start,
+                    end: command.end,
+                })
+            })
+            .collect()
+    }
+
+    /// The names of the defined functions, in the order of their indices.
+    pub fn functions(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.functions_list.iter().map(|(name, _)| *name)
+    }
+
+    /// Pops every finished frame whose body does not repeat.
+    fn unwind(&mut self) {
+        while let Some(frame) = self.frames.last() {
+            if frame.index < frame.commands.len() {
+                return;
+            }
+            let block = frame.block;
+            match block {
+                Block::While | Block::Loop if self.cell().is_nonzero() => return,
+                Block::Function => self.call_depth -= 1,
+                _ => {}
+            }
+            self.frames.pop();
+        }
+    }
+
+    fn enter(&mut self, block: Block, command: &'a SpannedCommand<'a>, commands: &'a [SpannedCommand<'a>]) {
+        self.frames.push(Frame {
+            block,
+            command: Some(command),
+            function: None,
+            commands,
+            index: 0,
+        });
+    }
+
+    fn execute(&mut self, command: &'a SpannedCommand<'a>) -> Result<(), RuntimeError<T>> {
+        match &command.command {
+            Command::AddInteger(i) => {
+                self.move_head(1, command)?;
+                self.set_cell(T::from(*i), command)?;
+            }
+            Command::AddFloat(f) => {
+                self.move_head(1, command)?;
+                self.set_cell(T::from_f64(*f), command)?;
+            }
+            Command::AddString(s) => {
+                for c in s.chars() {
+                    self.move_head(1, command)?;
+                    self.set_cell(T::from_char(c), command)?;
+                }
+            }
+            Command::WriteInteger(i) => {
+                self.set_cell(T::from(*i), command)?;
+            }
+            Command::WriteFloat(f) => {
+                self.set_cell(T::from_f64(*f), command)?;
+            }
+            Command::WriteString(s) => {
+                for c in s.chars() {
+                    self.set_cell(T::from_char(c), command)?;
+                    self.move_head(1, command)?;
+                }
+            }
+            Command::MoveLeft => {
+                self.move_head(-1, command)?;
+            }
+            Command::MoveRight => {
+                self.move_head(1, command)?;
+            }
+            // A negative cell moves the head the other way.
+            Command::ReadMoveLeft => {
+                let offset = self.cell().to_i128().saturating_neg();
+                self.move_head(offset, command)?;
+            }
+            Command::ReadMoveRight => {
+                let offset = self.cell().to_i128();
+                self.move_head(offset, command)?;
+            }
+            Command::MoveNLeft(n) => {
+                self.move_head(-(*n as i128), command)?;
+            }
+            Command::MoveNRight(n) => {
+                self.move_head(*n as i128, command)?;
+            }
+            Command::Increment => {
+                self.record_read(self.head);
+                let result = self.tape.borrow_mut().increment(self.head, self.config.overflow_mode);
+                result.map_err(|error| self.error(error.into(), command))?;
+                self.record_write(self.head);
+            }
+            Command::Decrement => {
+                self.record_read(self.head);
+                let result = self.tape.borrow_mut().decrement(self.head, self.config.overflow_mode);
+                result.map_err(|error| self.error(error.into(), command))?;
+                self.record_write(self.head);
+            }
+            Command::LeftAdd(offset) => {
+                self.operate(command, Direction::Left, *offset, Operation::Add)?;
+            }
+            Command::RightAdd(offset) => {
+                self.operate(command, Direction::Right, *offset, Operation::Add)?;
+            }
+            Command::LeftSubtract(offset) => {
+                self.operate(command, Direction::Left, *offset, Operation::Subtract)?;
+            }
+            Command::RightSubtract(offset) => {
+                self.operate(command, Direction::Right, *offset, Operation::Subtract)?;
+            }
+            Command::LeftMultiply(offset) => {
+                self.operate(command, Direction::Left, *offset, Operation::Multiply)?;
+            }
+            Command::RightMultiply(offset) => {
+                self.operate(command, Direction::Right, *offset, Operation::Multiply)?;
+            }
+            Command::LeftDivide(offset) => {
+                self.operate(command, Direction::Left, *offset, Operation::Divide)?;
+            }
+            Command::RightDivide(offset) => {
+                self.operate(command, Direction::Right, *offset, Operation::Divide)?;
+            }
+            Command::LeftModulo(offset) => {
+                self.operate(command, Direction::Left, *offset, Operation::Modulo)?;
+            }
+            Command::RightModulo(offset) => {
+                self.operate(command, Direction::Right, *offset, Operation::Modulo)?;
+            }
+            Command::If(if_commands, else_commands) => {
+                if self.cell().is_nonzero() {
+                    self.enter(Block::If, command, if_commands);
+                } else if let Some(else_commands) = else_commands {
+                    self.enter(Block::If, command, else_commands);
+                }
+            }
+            Command::While(while_commands) => {
+                if self.cell().is_nonzero() {
+                    self.enter(Block::While, command, while_commands);
+                }
+            }
+            Command::Loop(loop_commands) => {
+                self.enter(Block::Loop, command, loop_commands);
+            }
+            Command::FunctionDefinition(name, commands) => {
+                let index = self.function_index(name, commands);
+                self.functions.insert(name, index);
+            }
+            Command::FunctionCall(name) => {
+                let Some(index) = self.functions.get(name) else {
+                    return Err(self.error(RuntimeErrorKind::UnknownFunction(name.to_string()), command));
+                };
+                self.call(*index, command)?;
+            }
+            Command::GetFunction(name) => {
+                let Some(index) = self.functions.get(name) else {
+                    return Err(self.error(RuntimeErrorKind::UnknownFunction(name.to_string()), command));
+                };
+                self.set_cell(T::from(*index as i64), command)?;
+            }
+            Command::CallFunction => {
+                let cell = self.cell();
+                let index = cell.to_u64() as usize;
+                // Rules out fractions and negative cells, which `to_u64` would round or wrap onto a valid index.
+                if index >= self.functions_list.len() || T::from(index as i64) != cell {
+                    return Err(self.error(RuntimeErrorKind::InvalidFunctionIndex(cell.to_string()), command));
+                }
+                self.call(index, command)?;
+            }
+            Command::OutputNumber => {
+                let value = self.cell();
+                let result = self.io.borrow_mut().write_number(&value);
+                result.map_err(|error| self.error(error.into(), command))?;
+            }
+            Command::OutputChar => {
+                let c = self.cell().to_char().unwrap_or(' ');
+                let result = self.io.borrow_mut().write_char(c);
+                result.map_err(|error| self.error(error.into(), command))?;
+            }
+            Command::ReadKey => {
+                let result = {
+                    let mut io = self.io.borrow_mut();
+                    io.flush().and_then(|_| io.read_char())
+                };
+                match result {
+                    Ok(Some(c)) => self.set_cell(T::from_char(c), command)?,
+                    Ok(None) => self.set_cell(self.config.eof_value, command)?,
+                    Err(error) => return Err(self.error(error.into(), command)),
+                }
+            }
+            Command::Comment => {}
+        }
+        Ok(())
+    }
+
+    /// The index of a definition, giving it the next index the first time it is loaded or run.
+    fn function_index(&mut self, name: &'a str, commands: &'a [SpannedCommand<'a>]) -> usize {
+        match self.functions_list.iter().position(|(_, body)| std::ptr::eq(*body, commands)) {
+            Some(index) => index,
+            None => {
+                self.functions_list.push((name, commands));
+                self.functions_list.len() - 1
+            }
+        }
+    }
+
+    fn call(&mut self, index: usize, command: &'a SpannedCommand<'a>) -> Result<(), RuntimeError<T>> {
+        if self.call_depth >= self.config.max_call_depth {
+            return Err(self.error(RuntimeErrorKind::StackOverflow(self.config.max_call_depth), command));
+        }
+        self.call_depth += 1;
+        let (name, commands) = self.functions_list[index];
+        self.enter(Block::Function, command, commands);
+        if let Some(frame) = self.frames.last_mut() {
+            frame.function = Some(name);
+        }
+        Ok(())
+    }
+
+    fn cell(&mut self) -> T {
+        self.record_read(self.head);
+        self.tape.borrow().get(self.head)
+    }
+
+    fn record_read(&mut self, index: isize) {
+        if self.tracer.is_some() {
+            let tape = self.tape.borrow();
+            let value = if tape.in_bounds(index) { tape.get(index) } else { T::default() };
+            self.reads.push((index, value));
+        }
+    }
+
+    fn record_write(&mut self, index: isize) {
+        if self.tracer.is_some() {
+            let value = self.tape.borrow().get(index);
+            self.writes.push((index, value));
+        }
+    }
+
+    fn operate(&mut self, command: &SpannedCommand, direction: Direction, offset: Option<usize>, operation: Operation) -> Result<(), RuntimeError<T>> {
+        let offset = offset.unwrap_or(1);
+        let target = match direction {
+            Direction::Left => offset_index(self.head, -(offset as i128)),
+            Direction::Right => offset_index(self.head, offset as i128),
+        };
+        let target = target.map_err(|error| self.error(error.into(), command))?;
+        self.record_read(self.head);
+        self.record_read(target);
+        let result = self.tape.borrow_mut().operate(self.head, direction, Some(offset), operation, self.config.overflow_mode);
+        result.map_err(|error| self.error(error.into(), command))?;
+        self.record_write(target);
+        Ok(())
+    }
+
+    /// Counts one step of `command` against the step limit and checks the deadline.
+    fn count_step(&mut self, command: &'a SpannedCommand<'a>) -> Result<(), RuntimeError<T>> {
+        self.last = Some(command);
+        if let Some(max_steps) = self.config.max_steps {
+            if self.steps >= max_steps {
+                return Err(self.error(RuntimeErrorKind::StepLimitExceeded(max_steps), command));
+            }
+        }
+        if self.io.borrow_mut().interrupted() {
+            return Err(self.error(RuntimeErrorKind::Interrupted, command));
+        }
+        if let (Some(timeout), Some(deadline)) = (self.config.timeout, self.deadline) {
+            if Instant::now() >= deadline {
+                return Err(self.error(RuntimeErrorKind::Timeout(timeout), command));
+            }
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn set_cell(&mut self, value: T, command: &SpannedCommand) -> Result<(), RuntimeError<T>> {
+        let result = self.tape.borrow_mut().set(self.head, value);
+        result.map_err(|error| self.error(error.into(), command))?;
+        self.record_write(self.head);
+        Ok(())
+    }
+
+    /// Moves the head by `offset` cells, growing the tape as needed. Tapes that cannot grow left keep the head at cell 0.
+    fn move_head(&mut self, offset: i128, command: &SpannedCommand) -> Result<(), RuntimeError<T>> {
+        let result = offset_index(self.head, offset)
+            .and_then(|index| self.tape.borrow_mut().reach(index).map(|()| index));
+        match result {
+            Ok(index) => self.head = index,
+            Err(TapeError::Underflow) => self.head = 0,
+            Err(error) => return Err(self.error(error.into(), command)),
+        }
+        Ok(())
+    }
+
+    /// The cells around the head.
+    pub fn snapshot(&self) -> TapeSnapshot<T> {
+        TapeSnapshot::new(&*self.tape.borrow(), self.head)
+    }
+
+    fn error(&self, kind: RuntimeErrorKind, command: &SpannedCommand) -> RuntimeError<T> {
+        RuntimeError {
+            kind,
+            start: command.start,
+            end: command.end,
+            tape: self.snapshot(),
+            stack: self.call_stack(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::MemoryIo;
+    use turing_definitions::parser::parse;
+    use turing_definitions::tape::{BoundedTape, InfiniteTape, SparseTape};
+
+    fn machine<'a>(program: &'a [SpannedCommand<'a>], input: &str, config: Config<i64>) -> (Machine<'a, i64>, Rc<RefCell<MemoryIo>>) {
+        let io = Rc::new(RefCell::new(MemoryIo::new(input)));
+        (Machine::new(program, config).with_io(io.clone()), io)
+    }
+
+    fn run(program: &str, input: &str) -> (Result<(), RuntimeError<i64>>, String) {
+        let commands = parse(program).unwrap();
+        let (mut machine, io) = machine(&commands, input, Config::default());
+        let result = machine.run();
+        let output = io.borrow().output().to_string();
+        (result, output)
+    }
+
+    #[test]
+    fn test_output() {
+        let (result, output) = run(r#""Hi\n" <2) , > , > , 42 ."#, "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "Hi\n42");
+    }
+
+    #[test]
+    fn test_read_input() {
+        let (result, output) = run("? , ? .", "ab");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "a98");
+    }
+
+    #[test]
+    fn test_read_past_eof() {
+        let (result, output) = run("? . 5 ? .", "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "00");
+
+        let commands = parse("? ? . ? .").unwrap();
+        let config = Config { eof_value: -1, ..Config::default() };
+        let (mut machine, io) = machine(&commands, "x", config);
+        machine.run().unwrap();
+        assert_eq!(io.borrow().output(), "-1-1");
+    }
+
+    #[test]
+    fn test_cell_types() {
+        fn output<T: Number + 'static>(program: &str) -> String {
+            let commands = parse(program).unwrap();
+            let io = Rc::new(RefCell::new(MemoryIo::new("")));
+            Machine::<T>::new(&commands, Config::default()).with_io(io.clone()).run().unwrap();
+            let output = io.borrow().output().to_string();
+            output
+        }
+
+        let program = r#""Hi" <1) , > , 127 + ."#;
+        assert_eq!(output::<i8>(program), "Hi-128");
+        assert_eq!(output::<i16>(program), "Hi128");
+        assert_eq!(output::<i32>(program), "Hi128");
+        assert_eq!(output::<f32>(program), "Hi128");
+        assert_eq!(output::<f64>(r#""Hi" <1) , > , 1.5 + ."#), "Hi2.5");
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let (result, output) = run("3 while . - end 0 if 1 . else 2 . end 1 loop - end .", "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "32120");
+    }
+
+    #[test]
+    fn test_function_runs_every_command() {
+        let (result, output) = run("fun count 1 . 2 . 3 . end count count", "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "123123");
+    }
+
+    #[test]
+    fn test_recursion_does_not_use_native_stack() {
+        let (result, output) = run("900 fun down - if down end end down .", "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "0");
+
+        let (result, _) = run("fun forever forever end forever", "");
+        assert_eq!(result.unwrap_err().kind, RuntimeErrorKind::StackOverflow(DEFAULT_MAX_CALL_DEPTH));
+    }
+
+    #[test]
+    fn test_step() {
+        let commands = parse("1 while - end 7").unwrap();
+        let (mut machine, _) = machine(&commands, "", Config::default());
+        assert_eq!(machine.current(), Some(&commands[0]));
+        assert_eq!(machine.step(), Ok(Status::Running));
+        assert_eq!((machine.head(), machine.tape().get(1)), (1, 1));
+        assert_eq!(machine.step(), Ok(Status::Running));
+        assert_eq!(machine.frames().len(), 2);
+        assert_eq!(machine.frames()[1].block, Block::While);
+        assert_eq!(machine.step(), Ok(Status::Running));
+        assert_eq!(machine.frames().len(), 1);
+        assert_eq!(machine.current(), Some(&commands[2]));
+        assert_eq!(machine.step(), Ok(Status::Halted));
+        assert_eq!((machine.head(), machine.tape().get(2)), (2, 7));
+        assert_eq!(machine.steps(), 4);
+        assert_eq!(machine.step(), Ok(Status::Halted));
+    }
+
+    #[test]
+    fn test_load_keeps_state() {
+        let first = parse("5 fun twice . . end").unwrap();
+        let second = parse("twice").unwrap();
+        let (mut machine, io) = machine(&first, "", Config::default());
+        machine.run().unwrap();
+        machine.load(&second);
+        machine.run().unwrap();
+        assert_eq!(io.borrow().output(), "55");
+        assert_eq!(machine.functions().collect::<Vec<_>>(), vec!["twice"]);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let commands = parse("1 while end").unwrap();
+        let config = Config { max_steps: Some(100), ..Config::default() };
+        let (mut machine, _) = machine(&commands, "", config);
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::StepLimitExceeded(100));
+        assert!(error.kind.is_limit());
+        assert_eq!((error.start, error.end), (2, 11));
+        assert_eq!(machine.steps(), 100);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let commands = parse("1 loop + end").unwrap();
+        let config = Config { timeout: Some(Duration::from_millis(10)), ..Config::default() };
+        let (mut machine, _) = machine(&commands, "", config);
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::Timeout(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_interrupted() {
+        struct InterruptingIo;
+        impl Io for InterruptingIo {
+            fn read_char(&mut self) -> std::io::Result<Option<char>> {
+                Ok(None)
+            }
+            fn write_number(&mut self, _number: &dyn std::fmt::Display) -> std::io::Result<()> {
+                Ok(())
+            }
+            fn write_char(&mut self, _c: char) -> std::io::Result<()> {
+                Ok(())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+            fn interrupted(&mut self) -> bool {
+                true
+            }
+        }
+
+        let commands = parse("1 2").unwrap();
+        let mut machine = Machine::<i64>::new(&commands, Config::default()).with_io(Rc::new(RefCell::new(InterruptingIo)));
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::Interrupted);
+        assert_eq!((error.start, error.end), (0, 1));
+    }
+
+    #[test]
+    fn test_resume_after_step_limit() {
+        let commands = parse("1 . 2 . 3 .").unwrap();
+        let config = Config { max_steps: Some(3), ..Config::default() };
+        let (mut machine, io) = machine(&commands, "", config);
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::StepLimitExceeded(3));
+        assert_eq!(machine.current(), Some(&commands[3]));
+        assert_eq!(io.borrow().output(), "1");
+    }
+
+    #[test]
+    fn test_function_frames() {
+        let commands = parse("fun inner 1 end fun outer inner end outer").unwrap();
+        let (mut machine, _) = machine(&commands, "", Config::default());
+        while machine.frames().len() < 3 {
+            machine.step().unwrap();
+        }
+        let functions: Vec<_> = machine.frames().iter().map(|frame| frame.function).collect();
+        assert_eq!(functions, vec![None, Some("outer"), Some("inner")]);
+        assert_eq!(machine.frames()[2].command.map(|command| &command.command), Some(&Command::FunctionCall("inner")));
+    }
+
+    #[test]
+    fn test_negative_cells() {
+        let commands = parse("5 <3) 7 . (2> .").unwrap();
+        let (machine, io) = machine(&commands, "", Config::default());
+        let mut machine = machine.with_tape(Rc::new(RefCell::new(InfiniteTape::<i64>::new())));
+        machine.run().unwrap();
+        assert_eq!(io.borrow().output(), "75");
+    }
+
+    #[test]
+    fn test_read_move_negative_cell() {
+        let commands = parse("[-2] |> 5 . [-3] <| .").unwrap();
+        let (machine, io) = machine(&commands, "", Config::default());
+        let mut machine = machine.with_tape(Rc::new(RefCell::new(InfiniteTape::<i64>::new())));
+        machine.run().unwrap();
+        assert_eq!(io.borrow().output(), "50");
+        assert_eq!(machine.head(), 2);
+    }
+
+    #[test]
+    fn test_read_move_too_far() {
+        let commands = parse("[9223372036854775807] |> .").unwrap();
+        let (unbounded, io) = machine(&commands, "", Config::default());
+        let mut unbounded = unbounded.with_tape(Rc::new(RefCell::new(InfiniteTape::<i64>::new())));
+        assert_eq!(unbounded.run().unwrap_err().kind, RuntimeErrorKind::OutOfMemory);
+        assert_eq!(io.borrow().output(), "");
+
+        let (bounded, _) = machine(&commands, "", Config::default());
+        let mut bounded = bounded.with_tape(Rc::new(RefCell::new(BoundedTape::new(InfiniteTape::<i64>::new(), 100))));
+        assert_eq!(bounded.run().unwrap_err().kind, RuntimeErrorKind::TapeLimitExceeded(100));
+        assert_eq!(bounded.head(), 0);
+    }
+
+    #[test]
+    fn test_head_out_of_range() {
+        // The snapshot keeps eight cells on either side of the head, as far as the tape goes.
+        for (program, head, cells) in [
+            ("[9223372036854775807] |> 5", isize::MAX, 9),
+            ("[9223372036854775807] |> >", isize::MAX, 9),
+            ("[9223372036854775807] |> +>", isize::MAX, 9),
+            ("[-9223372036854775808] |> <2)", isize::MIN, 9),
+            ("[-9223372036854775808] |> <+", isize::MIN, 9),
+            ("[-9223372036854775808] <|", 0, 17),
+        ] {
+            let commands = parse(program).unwrap();
+            let (machine, _) = machine(&commands, "", Config::default());
+            let mut machine = machine.with_tape(Rc::new(RefCell::new(SparseTape::<i64>::new())));
+            let error = machine.run().unwrap_err();
+            assert_eq!(error.kind, RuntimeErrorKind::TapeOutOfRange, "program {:?}", program);
+            assert_eq!(machine.head(), head);
+            assert_eq!(error.tape.head, head);
+            assert_eq!(error.tape.cells.len(), cells);
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let commands = parse("5 + 3 <+ while - end").unwrap();
+        let (machine, _) = machine(&commands, "", Config::default());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut machine = machine.with_tracer(events.clone());
+        machine.run().unwrap();
+
+        let events = events.borrow();
+        let summary: Vec<_> = events.iter()
+            .map(|event| (event.step, event.kind, event.head_before, event.head_after))
+            .collect();
+        assert_eq!(summary[..4], [
+            (1, "add_integer", 0, 1),
+            (2, "increment", 1, 1),
+            (3, "add_integer", 1, 2),
+            (4, "left_add", 2, 2),
+        ]);
+        assert_eq!(events[0].writes, [(1, 5)]);
+        assert_eq!(events[1].reads, [(1, 5)]);
+        assert_eq!(events[1].writes, [(1, 6)]);
+        assert_eq!(events[3].reads, [(2, 3), (1, 6)]);
+        assert_eq!(events[3].writes, [(1, 9)]);
+        assert_eq!((events[3].start, events[3].end), (6, 8));
+        // The `while` reads its condition, and the last `-` reads the cell that stops it repeating.
+        assert_eq!(events[4].reads, [(2, 3)]);
+        assert_eq!(events.last().unwrap().reads, [(2, 1), (2, 0)]);
+        assert_eq!(events.len() as u64, machine.steps());
+    }
+
+    #[test]
+    fn test_call_dispatches_on_cell() {
+        let (result, output) = run("fun a 1 . end fun b 2 . end getfun b call getfun a call", "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "21");
+
+        // Indices can be stored, moved and computed like any other value.
+        let (result, output) = run("fun a 1 . end fun b 2 . end getfun a + call", "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "2");
+    }
+
+    #[test]
+    fn test_call_function_defined_after_call_site() {
+        let (result, output) = run("fun apply call end fun later 7 . end getfun later apply", "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "7");
+    }
+
+    #[test]
+    fn test_call_invalid_index() {
+        for (program, value) in [("fun a end 1 call", "1"), ("-1 call", "-1"), ("call", "0")] {
+            let (result, _) = run(program, "");
+            let error = result.unwrap_err();
+            assert_eq!(error.kind, RuntimeErrorKind::InvalidFunctionIndex(value.to_string()));
+            assert_eq!(&program[error.start..error.end], "call");
+        }
+
+        let commands = parse("fun a 1 . end 0.5 call").unwrap();
+        let mut machine = Machine::<f64>::new(&commands, Config::default()).with_io(Rc::new(RefCell::new(MemoryIo::new(""))));
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::InvalidFunctionIndex(String::from("0.5")));
+    }
+
+    #[test]
+    fn test_functions_hoisted() {
+        let (result, output) = run("greet fun greet \"hi\" < , > , end", "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "hi");
+
+        let program = "4 even fun even if - odd else 1 . end end fun odd if - even else 0 . end end";
+        let (result, output) = run(program, "");
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "1");
+    }
+
+    #[test]
+    fn test_redefinition() {
+        let commands = parse("f fun f 2 . end f fun f 3 . end getfun f f").unwrap();
+        let (mut machine, io) = machine(&commands, "", Config::default());
+        machine.run().unwrap();
+        // The second definition takes over `f` once its `fun` runs, and each definition keeps one index.
+        assert_eq!(io.borrow().output(), "223");
+        assert_eq!(machine.functions().count(), 2);
+        assert_eq!(machine.tape().get(2), 1);
+    }
+
+    #[test]
+    fn test_max_call_depth() {
+        let commands = parse("fun down - if down end end down .").unwrap();
+        let config = Config { max_call_depth: 100_000, ..Config::default() };
+        let (deep, io) = machine(&commands, "", config);
+        let mut deep = deep.with_tape(Rc::new(RefCell::new(vec![50_000])));
+        assert_eq!(deep.run(), Ok(()));
+        assert_eq!(io.borrow().output(), "0");
+
+        let config = Config { max_call_depth: 3, ..Config::default() };
+        let (shallow, _) = machine(&commands, "", config);
+        let mut shallow = shallow.with_tape(Rc::new(RefCell::new(vec![4])));
+        let error = shallow.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::StackOverflow(3));
+        assert_eq!(error.stack.len(), 3);
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let program = "fun outer 1 inner end fun inner 0 <% end\nouter";
+        let commands = parse(program).unwrap();
+        let (mut machine, _) = machine(&commands, "", Config::default());
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+        let stack: Vec<_> = error.stack.iter()
+            .map(|frame| (frame.function.as_str(), &program[frame.start..frame.end]))
+            .collect();
+        assert_eq!(stack, [("inner", "inner"), ("outer", "outer")]);
+        assert_eq!(machine.call_stack(), error.stack);
+    }
+}
@@ -0,0 +1,280 @@
+use turing_definitions::ast::{Command, SpannedCommand};
+use turing_definitions::{Direction, Number, OverflowMode};
+use crate::bytecode::Instruction;
+
+/// Whether `T` holds integers, which truncate a half to zero.
+fn is_integer<T: Number>() -> bool {
+    T::from_f64(0.5).is_zero()
+}
+
+/// Whether adding `n` once gives the same cell as adding 1 `n` times, and counting down always reaches zero.
+///
+/// Every operation overflows under a modulus of 0 or one too large for the cell type, so it is not exact.
+fn exact_arithmetic<T: Number>(mode: OverflowMode) -> bool {
+    is_integer::<T>() && match mode {
+        OverflowMode::Wrapping => true,
+        OverflowMode::Modular(modulus) => modulus != 0 && modulus <= T::max_modulus(),
+        OverflowMode::Saturating | OverflowMode::Checked => false,
+    }
+}
+
+/// Whether arithmetic wraps around the size of a cell, so a loop's effect can be multiplied out.
+fn wraps<T: Number>(mode: OverflowMode) -> bool {
+    is_integer::<T>() && matches!(mode, OverflowMode::Wrapping)
+}
+
+fn movement(command: &Command) -> Option<(Direction, u64)> {
+    match command {
+        Command::MoveLeft => Some((Direction::Left, 1)),
+        Command::MoveRight => Some((Direction::Right, 1)),
+        Command::MoveNLeft(n) => Some((Direction::Left, *n)),
+        Command::MoveNRight(n) => Some((Direction::Right, *n)),
+        _ => None,
+    }
+}
+
+fn change(command: &Command) -> Option<i64> {
+    match command {
+        Command::Increment => Some(1),
+        Command::Decrement => Some(-1),
+        _ => None,
+    }
+}
+
+/// Folds the run of `+` and `-`, or of moves in one direction, that `commands` starts with into one instruction,
+/// returning it with the number of commands it stands for. The instruction's jump target is left for the caller.
+///
+/// Moves in both directions are not folded, since a tape that cannot grow left stops the head at cell 0.
+pub(crate) fn fold_run<T: Number>(commands: &[SpannedCommand], mode: OverflowMode) -> Option<(Instruction<T>, usize)> {
+    if let Some((direction, _)) = movement(&commands.first()?.command) {
+        let moves: Vec<u64> = commands.iter()
+            .map_while(|command| movement(&command.command).filter(|(d, _)| *d == direction).map(|(_, n)| n))
+            .collect();
+        if moves.len() < 2 {
+            return None;
+        }
+        let n = moves.iter().fold(0u64, |total, n| total.saturating_add(*n));
+        return Some((Instruction::MoveRun(direction, n, 0), moves.len()));
+    }
+
+    if !exact_arithmetic::<T>(mode) {
+        return None;
+    }
+    let changes: Vec<i64> = commands.iter().map_while(|command| change(&command.command)).collect();
+    if changes.len() < 2 {
+        return None;
+    }
+    let net: i64 = changes.iter().sum();
+    let amount = T::from(net.abs());
+    if amount.to_u64() != net.unsigned_abs() {
+        return None;
+    }
+    // A net change of zero still reduces the cell under a modulus, so it is kept.
+    let instruction = if net < 0 { Instruction::Subtract(amount, 0) } else { Instruction::Add(amount, 0) };
+    Some((instruction, changes.len()))
+}
+
+/// Whether a `while` loop with this body always ends by clearing its cell, for `while - end` and `while + end`.
+pub(crate) fn clears<T: Number>(body: &[SpannedCommand], mode: OverflowMode) -> bool {
+    matches!(body, [command] if change(&command.command).is_some()) && exact_arithmetic::<T>(mode)
+}
+
+/// Whether a `while` loop with this body prints a string, for `while , > end`.
+pub(crate) fn prints(body: &[SpannedCommand]) -> bool {
+    matches!(body, [output, right] if output.command == Command::OutputChar && right.command == Command::MoveRight)
+}
+
+/// A [`Transfer`](Instruction::Transfer) for a `while` loop whose body only moves and changes cells, coming back to
+/// the cell it started on after decrementing it once.
+pub(crate) fn transfer<T: Number>(body: &[SpannedCommand], mode: OverflowMode) -> Option<Instruction<T>> {
+    if !wraps::<T>(mode) {
+        return None;
+    }
+    let mut offset: isize = 0;
+    let mut own = 0;
+    let mut targets: Vec<(isize, i64)> = Vec::new();
+    let mut path = vec![0];
+    for command in body {
+        if let Some(change) = change(&command.command) {
+            if offset == 0 {
+                own += change;
+            } else if let Some(target) = targets.iter_mut().find(|(target, _)| *target == offset) {
+                target.1 += change;
+            } else {
+                targets.push((offset, change));
+            }
+        } else if let Some((direction, n)) = movement(&command.command) {
+            let n = isize::try_from(n).ok()?;
+            offset = match direction {
+                Direction::Left => offset.checked_sub(n)?,
+                Direction::Right => offset.checked_add(n)?,
+            };
+            path.push(offset);
+        } else {
+            return None;
+        }
+    }
+    if offset != 0 || own != -1 || targets.is_empty() {
+        return None;
+    }
+    // The loop grows the tape as far as it moves, even past the cells it changes, and one end might be out of reach
+    // only once it has grown towards the other.
+    let (leftmost, rightmost) = (*path.iter().min()?, *path.iter().max()?);
+    let mut ends = [leftmost, rightmost];
+    ends.sort_by_key(|end| path.iter().position(|offset| offset == end));
+    for end in ends.into_iter().rev().filter(|end| *end != 0) {
+        let factor = targets.iter().position(|(target, _)| *target == end).map_or(0, |i| targets.remove(i).1);
+        targets.insert(0, (end, factor));
+    }
+    Some(Instruction::Transfer(targets.into_iter().map(|(target, factor)| (target, T::from(factor))).collect()))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use turing_definitions::parser::parse;
+    use turing_definitions::tape::{BoundedTape, InfiniteTape, SparseTape};
+    use crate::bytecode::{compile, compile_optimized};
+    use crate::machine::Config;
+    use crate::testing::{assert_same, infinite_tape, shared, sparse_tape, vec_tape, SharedTape};
+    use crate::Vm;
+
+    /// Runs `program` on every kind of tape, including ones too small for some programs, so errors are compared too.
+    fn assert_same_on_tapes<T: Number + 'static>(program: &str, mode: OverflowMode) {
+        let tapes: [&dyn Fn() -> SharedTape<T>; 6] = [
+            &vec_tape::<T>,
+            &infinite_tape::<T>,
+            &sparse_tape::<T>,
+            &|| shared(BoundedTape::new(vec![T::default()], 3)),
+            &|| shared(BoundedTape::new(InfiniteTape::<T>::new(), 3)),
+            &|| shared(BoundedTape::new(SparseTape::<T>::new(), 3)),
+        ];
+        for tape in tapes {
+            let config = Config { overflow_mode: mode, ..Config::default() };
+            assert_same(program, "ab", config, tape, true);
+        }
+    }
+
+    const PROGRAMS: &[&str] = &[
+        "5 + + + - + . > > > (2> < . 1 . < < <3) .",
+        "3 - - - - - . 0 - - . + + + + .",
+        "300 while - end . -2 while + end . 0 while - end .",
+        r#""Hi\n" <3) while , > end 7 ."#,
+        "0 while , > end .",
+        "5 while - > + + < end . > .",
+        "5 while - > + > +++ < < end . > . > .",
+        "5 while > + < - end . > .",
+        "5 while - < + > end . < .",
+        "2 3 while - < < + > > end < .",
+        "3 while - < < + > > end . < . < .",
+        "1 5 while - > (3> + <4) end . > .",
+        "5 while - >> + <<< + > end . < . >>> .",
+        "6 while - - > + < end .",
+        "4 while - > + < + - - end .",
+        "2 0 while - > + < end . > .",
+        "1 while > > + < < - > + - < end .",
+        "7 while - > + < . end > .",
+        "fun clear while - end end 9 clear . 100 + + + clear .",
+        "? + + + , > ? - - - - , if while - end end .",
+        "127 + + . 0 - - - .",
+        "0.5 + + . 1.5 while - end .",
+        "7 > > > > > .",
+        "0 - + + - + < < . > > > > .",
+        r#""abc" <3) while , > end > > 1 ."#,
+        "5 while - < < > > > + < end . > .",
+    ];
+
+    #[test]
+    fn test_same_as_machine() {
+        let modes = [
+            OverflowMode::Wrapping,
+            OverflowMode::Saturating,
+            OverflowMode::Checked,
+            OverflowMode::Modular(0),
+            OverflowMode::Modular(256),
+            OverflowMode::Modular(7),
+        ];
+        for program in PROGRAMS {
+            for mode in modes {
+                assert_same_on_tapes::<i64>(program, mode);
+                assert_same_on_tapes::<i8>(program, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_as_machine_with_large_modulus() {
+        for program in PROGRAMS {
+            assert_same_on_tapes::<i8>(program, OverflowMode::Modular(1000));
+        }
+    }
+
+    #[test]
+    fn test_same_as_machine_on_floats() {
+        for program in PROGRAMS.iter().filter(|program| !program.contains("while -") && !program.contains("while +")) {
+            for mode in [OverflowMode::Wrapping, OverflowMode::Saturating, OverflowMode::Checked] {
+                assert_same_on_tapes::<f64>(program, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_folds() {
+        let commands = parse("+ + - + > (2> > < <2) - while - end while , > end while - > + + < end").unwrap();
+        let optimized = compile_optimized::<i64>(&commands, OverflowMode::Wrapping);
+        // Each folded instruction is followed by the commands it stands for, which it skips.
+        assert_eq!(optimized.instructions[..21], [
+            Instruction::Add(2, 5),
+            Instruction::Increment,
+            Instruction::Increment,
+            Instruction::Decrement,
+            Instruction::Increment,
+            Instruction::MoveRun(Direction::Right, 4, 9),
+            Instruction::MoveRight(1),
+            Instruction::MoveRight(2),
+            Instruction::MoveRight(1),
+            Instruction::MoveRun(Direction::Left, 3, 12),
+            Instruction::MoveLeft(1),
+            Instruction::MoveLeft(2),
+            Instruction::Decrement,
+            Instruction::Set(0),
+            Instruction::Print(16),
+            Instruction::Branch(19),
+            Instruction::OutputChar,
+            Instruction::MoveRight(1),
+            Instruction::Repeat(16),
+            Instruction::Transfer(Box::new([(1, 2)])),
+            Instruction::Branch(28),
+        ]);
+
+        // Every operation overflows under a modulus too large for the cell type, so neither the run nor the loop is
+        // folded.
+        let commands = parse("127 + + while - end").unwrap();
+        let large = compile_optimized::<i8>(&commands, OverflowMode::Modular(1000));
+        assert_eq!(large.instructions, compile::<i8>(&commands).instructions);
+
+        // Floats only get their moves folded, since their loops might never reach zero.
+        let commands = parse("+ + - + > (2> > < <2) - while - end while , > end while - > + + < end").unwrap();
+        let floats = compile_optimized::<f64>(&commands, OverflowMode::Wrapping);
+        let unoptimized = compile::<f64>(&commands);
+        assert_eq!(floats.instructions[..3], unoptimized.instructions[..3]);
+        assert!(!floats.instructions.iter().any(|instruction| matches!(instruction, Instruction::Transfer(_))));
+    }
+
+    #[test]
+    fn test_transfer_reaches_ends_in_order() {
+        let commands = parse("while - < < > > > + < end").unwrap();
+        let optimized = compile_optimized::<i64>(&commands, OverflowMode::Wrapping);
+        assert_eq!(optimized.instructions[0], Instruction::Transfer(Box::new([(-2, 0), (1, 1)])));
+    }
+
+    #[test]
+    fn test_fewer_steps() {
+        let commands = parse("100000 while - > + + < end > while - end").unwrap();
+        let mut vm = Vm::<i64>::new(compile_optimized(&commands, OverflowMode::Wrapping), Config::default());
+        vm.run().unwrap();
+        assert_eq!(vm.steps(), 5);
+        assert_eq!(vm.tape().get(1), 0);
+    }
+}
@@ -0,0 +1,70 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use turing_definitions::parser::parse;
+use turing_definitions::tape::{InfiniteTape, SparseTape};
+use turing_definitions::{Number, Tape};
+use crate::bytecode::{compile, compile_optimized};
+use crate::error::{RuntimeError, TapeSnapshot};
+use crate::io::MemoryIo;
+use crate::machine::Config;
+use crate::{Machine, Vm};
+
+pub(crate) type SharedTape<T> = Rc<RefCell<dyn Tape<T>>>;
+
+/// Everything a run leaves behind.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Outcome<T> {
+    pub result: Result<(), RuntimeError<T>>,
+    pub output: String,
+    pub steps: u64,
+    pub head: isize,
+    pub tape: TapeSnapshot<T>,
+}
+
+pub(crate) fn shared<T: Number>(tape: impl Tape<T> + 'static) -> SharedTape<T> {
+    Rc::new(RefCell::new(tape))
+}
+
+pub(crate) fn vec_tape<T: Number + 'static>() -> SharedTape<T> {
+    shared(vec![T::default()])
+}
+
+pub(crate) fn infinite_tape<T: Number + 'static>() -> SharedTape<T> {
+    shared(InfiniteTape::<T>::new())
+}
+
+pub(crate) fn sparse_tape<T: Number + 'static>() -> SharedTape<T> {
+    shared(SparseTape::<T>::new())
+}
+
+pub(crate) fn run_machine<T: Number + 'static>(program: &str, input: &str, config: Config<T>, tape: SharedTape<T>) -> Outcome<T> {
+    let commands = parse(program).unwrap();
+    let io = Rc::new(RefCell::new(MemoryIo::new(input)));
+    let mut machine = Machine::new(&commands, config).with_tape(tape).with_io(io.clone());
+    let result = machine.run();
+    let output = io.borrow().output().to_string();
+    Outcome { result, output, steps: machine.steps(), head: machine.head(), tape: machine.snapshot() }
+}
+
+/// Runs the program compiled by [`compile`], or by [`compile_optimized`] for the config's overflow mode.
+pub(crate) fn run_vm<T: Number + 'static>(program: &str, input: &str, config: Config<T>, tape: SharedTape<T>, optimized: bool) -> Outcome<T> {
+    let commands = parse(program).unwrap();
+    let io = Rc::new(RefCell::new(MemoryIo::new(input)));
+    let compiled = if optimized { compile_optimized(&commands, config.overflow_mode) } else { compile(&commands) };
+    let mut vm = Vm::new(compiled, config).with_tape(tape).with_io(io.clone());
+    let result = vm.run();
+    let output = io.borrow().output().to_string();
+    let tape = TapeSnapshot::new(&*vm.tape(), vm.head());
+    Outcome { result, output, steps: vm.steps(), head: vm.head(), tape }
+}
+
+/// Checks that a `Vm` leaves the same outcome as a `Machine`, each given a new tape. Optimized programs take fewer
+/// steps, so their steps are not compared.
+pub(crate) fn assert_same<T: Number + 'static>(program: &str, input: &str, config: Config<T>, tape: impl Fn() -> SharedTape<T>, optimized: bool) {
+    let mut expected = run_machine(program, input, config.clone(), tape());
+    let actual = run_vm(program, input, config.clone(), tape(), optimized);
+    if optimized {
+        expected.steps = actual.steps;
+    }
+    assert_eq!(actual, expected, "program {:?} under {:?}", program, config.overflow_mode);
+}
@@ -0,0 +1,122 @@
+use std::fmt::Display;
+use std::io::Write;
+
+/// A cell index and the value read from or written to it.
+pub type CellAccess<T> = (isize, T);
+
+/// One step of a [`Machine`](crate::Machine), as passed to a [`Tracer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent<T> {
+    /// The number of the step, counting from 1.
+    pub step: u64,
+    pub start: usize,
+    pub end: usize,
+    /// The kind of command executed, as given by [`Command::kind`](turing_definitions::ast::Command::kind).
+    pub kind: &'static str,
+    pub head_before: isize,
+    pub head_after: isize,
+    /// Cells read by the step, in the order they were read, including the check that ends or repeats a loop.
+    pub reads: Vec<CellAccess<T>>,
+    /// Cells written by the step, with their new values.
+    pub writes: Vec<CellAccess<T>>,
+}
+
+/// Receives every step a machine executes.
+pub trait Tracer<T> {
+    fn trace(&mut self, event: &TraceEvent<T>) -> std::io::Result<()>;
+
+    /// Called when the program halts or stops with an error.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects the events, for inspecting a run afterwards.
+impl<T: Clone> Tracer<T> for Vec<TraceEvent<T>> {
+    fn trace(&mut self, event: &TraceEvent<T>) -> std::io::Result<()> {
+        self.push(event.clone());
+        Ok(())
+    }
+}
+
+/// Writes each event as a line of JSON.
+///
+/// ```text
+/// {"step":1,"span":[0,1],"kind":"add_integer","head_before":0,"head_after":1,"reads":[],"writes":[{"cell":1,"value":5}]}
+/// ```
+#[derive(Debug)]
+pub struct JsonLinesTracer<W> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> JsonLinesTracer<W> {
+        JsonLinesTracer {
+            writer,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<T: Display, W: Write> Tracer<T> for JsonLinesTracer<W> {
+    fn trace(&mut self, event: &TraceEvent<T>) -> std::io::Result<()> {
+        write!(
+            self.writer,
+            r#"{{"step":{},"span":[{},{}],"kind":"{}","head_before":{},"head_after":{},"reads":{},"writes":{}}}"#,
+            event.step, event.start, event.end, event.kind, event.head_before, event.head_after,
+            json_cells(&event.reads), json_cells(&event.writes),
+        )?;
+        writeln!(self.writer)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn json_cells<T: Display>(cells: &[CellAccess<T>]) -> String {
+    let cells: Vec<String> = cells.iter()
+        .map(|(index, value)| format!(r#"{{"cell":{},"value":{}}}"#, index, json_number(value)))
+        .collect();
+    format!("[{}]", cells.join(","))
+}
+
+/// JSON has no infinities or NaN, so those float cells are written as `null`.
+fn json_number<T: Display>(value: &T) -> String {
+    let text = value.to_string();
+    match text.parse::<f64>() {
+        Ok(number) if number.is_finite() => text,
+        _ => String::from("null"),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_json_lines() {
+        let mut tracer = JsonLinesTracer::new(Vec::new());
+        let event = TraceEvent {
+            step: 3,
+            start: 4,
+            end: 6,
+            kind: "right_add",
+            head_before: 1,
+            head_after: 1,
+            reads: vec![(1, 2.5), (2, f64::NAN)],
+            writes: vec![(2, -1.0)],
+        };
+        tracer.trace(&event).unwrap();
+        tracer.trace(&TraceEvent { reads: Vec::new(), writes: Vec::new(), ..event }).unwrap();
+        let output = String::from_utf8(tracer.into_inner()).unwrap();
+        assert_eq!(output, concat!(
+            r#"{"step":3,"span":[4,6],"kind":"right_add","head_before":1,"head_after":1,"reads":[{"cell":1,"value":2.5},{"cell":2,"value":null}],"writes":[{"cell":2,"value":-1}]}"#, "\n",
+            r#"{"step":3,"span":[4,6],"kind":"right_add","head_before":1,"head_after":1,"reads":[],"writes":[]}"#, "\n",
+        ));
+    }
+}
@@ -0,0 +1,518 @@
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+use std::time::Instant;
+use turing_definitions::{offset_index, Direction, Number, Operation, OverflowMode, Tape, TapeError};
+use crate::bytecode::{Instruction, Program};
+use crate::error::{RuntimeError, RuntimeErrorKind, StackFrame, TapeSnapshot};
+use crate::io::{Io, StreamIo};
+use crate::machine::Config;
+
+/// How many steps run between checks of [`Io::interrupted`] and the timeout, which are too slow to make every step.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// A function call waiting to return.
+#[derive(Debug, Clone, Copy)]
+struct Call {
+    function: usize,
+    /// The instruction that made the call.
+    from: usize,
+}
+
+/// Runs a compiled [`Program`] in a single loop, keeping calls on an explicit stack.
+///
+/// It behaves like a [`Machine`](crate::Machine) running the same commands, with the same step counts and errors,
+/// but cannot be stepped, traced or given more code. A program from
+/// [`compile_optimized`](crate::compile_optimized) has the same effect in fewer steps.
+pub struct Vm<T: Number> {
+    program: Program<T>,
+    tape: Rc<RefCell<dyn Tape<T>>>,
+    head: isize,
+    pc: usize,
+    calls: Vec<Call>,
+    /// The function each name points at.
+    names: Vec<Option<usize>>,
+    /// The index `getfun` gives each function, once it has been defined.
+    indices: Vec<Option<usize>>,
+    /// The functions in the order of their indices.
+    defined: Vec<usize>,
+    steps: u64,
+    deadline: Option<Instant>,
+    io: Rc<RefCell<dyn Io>>,
+    config: Config<T>,
+}
+
+impl<T: Number + 'static> Vm<T> {
+    /// Creates a VM with an empty tape that only grows right, reading stdin and writing stdout.
+    pub fn new(program: Program<T>, config: Config<T>) -> Vm<T> {
+        let mut vm = Vm {
+            tape: Rc::new(RefCell::new(vec![T::default()])),
+            head: 0,
+            pc: 0,
+            calls: Vec::new(),
+            names: vec![None; program.names.len()],
+            indices: vec![None; program.functions.len()],
+            defined: Vec::new(),
+            steps: 0,
+            deadline: config.timeout.and_then(|timeout| Instant::now().checked_add(timeout)),
+            io: Rc::new(RefCell::new(StreamIo::stdio())),
+            config,
+            program,
+        };
+        for function in vm.program.hoisted.clone() {
+            let name = vm.program.functions[function].name;
+            vm.define(function);
+            vm.names[name].get_or_insert(function);
+        }
+        vm
+    }
+
+    pub fn with_tape(self, tape: Rc<RefCell<dyn Tape<T>>>) -> Vm<T> {
+        Self {
+            tape,
+            head: 0,
+            ..self
+        }
+    }
+
+    pub fn with_io(self, io: Rc<RefCell<dyn Io>>) -> Vm<T> {
+        Self {
+            io,
+            ..self
+        }
+    }
+}
+
+impl<T: Number> Vm<T> {
+    /// Runs the program until it halts or fails.
+    ///
+    /// A program stopped by a step limit, timeout or interrupt carries on from the same instruction when run again.
+    pub fn run(&mut self) -> Result<(), RuntimeError<T>> {
+        let result = self.execute();
+        let flushed = self.io.borrow_mut().flush();
+        if let Err(error) = flushed {
+            result?;
+            return Err(self.error(error.into(), self.pc));
+        }
+        result
+    }
+
+    pub fn tape(&self) -> Ref<'_, dyn Tape<T>> {
+        self.tape.borrow()
+    }
+
+    pub fn head(&self) -> isize {
+        self.head
+    }
+
+    /// The number of steps executed, counted as a [`Machine`](crate::Machine) counts them.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn program(&self) -> &Program<T> {
+        &self.program
+    }
+
+    fn execute(&mut self) -> Result<(), RuntimeError<T>> {
+        // Held for the whole run rather than borrowed for every instruction.
+        let tape = self.tape.clone();
+        let mut tape = tape.borrow_mut();
+        let tape = &mut *tape;
+        let io = self.io.clone();
+        let mut io = io.borrow_mut();
+        let mode = self.config.overflow_mode;
+
+        loop {
+            let pc = self.pc;
+            let instruction = &self.program.instructions[pc];
+            let counts = match instruction {
+                Instruction::Jump(_) | Instruction::Return | Instruction::Halt => false,
+                Instruction::Repeat(_) => tape.get(self.head).is_nonzero(),
+                _ => true,
+            };
+            if counts {
+                if let Some(max_steps) = self.config.max_steps {
+                    if self.steps >= max_steps {
+                        return Err(self.fail(RuntimeErrorKind::StepLimitExceeded(max_steps), tape));
+                    }
+                }
+                if self.steps.is_multiple_of(CHECK_INTERVAL) {
+                    if io.interrupted() {
+                        return Err(self.fail(RuntimeErrorKind::Interrupted, tape));
+                    }
+                    if let (Some(timeout), Some(deadline)) = (self.config.timeout, self.deadline) {
+                        if Instant::now() >= deadline {
+                            return Err(self.fail(RuntimeErrorKind::Timeout(timeout), tape));
+                        }
+                    }
+                }
+                self.steps += 1;
+            }
+
+            let mut next = pc + 1;
+            let result = match instruction {
+                Instruction::MoveSet(value) => {
+                    move_head(tape, &mut self.head, 1).and_then(|()| tape.set(self.head, *value))
+                }
+                Instruction::MoveSetEach(values) => values.iter().try_for_each(|value| {
+                    move_head(tape, &mut self.head, 1)?;
+                    tape.set(self.head, *value)
+                }),
+                Instruction::Set(value) => tape.set(self.head, *value),
+                Instruction::SetEach(values) => values.iter().try_for_each(|value| {
+                    tape.set(self.head, *value)?;
+                    move_head(tape, &mut self.head, 1)
+                }),
+                Instruction::MoveLeft(n) => move_head(tape, &mut self.head, -(*n as i128)),
+                Instruction::MoveRight(n) => move_head(tape, &mut self.head, *n as i128),
+                Instruction::ReadMove(direction) => {
+                    let offset = tape.get(self.head).to_i128();
+                    let offset = match direction {
+                        Direction::Left => offset.saturating_neg(),
+                        Direction::Right => offset,
+                    };
+                    move_head(tape, &mut self.head, offset)
+                }
+                Instruction::Increment => tape.increment(self.head, mode),
+                Instruction::Decrement => tape.decrement(self.head, mode),
+                Instruction::MoveRun(direction, n, end) => {
+                    let offset = match direction {
+                        Direction::Left => -(*n as i128),
+                        Direction::Right => *n as i128,
+                    };
+                    if move_head(tape, &mut self.head, offset).is_ok() {
+                        next = *end;
+                    }
+                    Ok(())
+                }
+                Instruction::Add(value, end) => add(tape, self.head, Operation::Add, *value, mode).map(|added| {
+                    if added {
+                        next = *end;
+                    }
+                }),
+                Instruction::Subtract(value, end) => add(tape, self.head, Operation::Subtract, *value, mode).map(|added| {
+                    if added {
+                        next = *end;
+                    }
+                }),
+                Instruction::Transfer(targets) => transfer(tape, self.head, targets),
+                Instruction::Print(body) => loop {
+                    let value = tape.get(self.head);
+                    if value.is_zero() {
+                        break Ok(());
+                    }
+                    if io.write_char(value.to_char().unwrap_or(' ')).is_err() {
+                        next = *body;
+                        break Ok(());
+                    }
+                    if move_head(tape, &mut self.head, 1).is_err() {
+                        next = *body + 1;
+                        break Ok(());
+                    }
+                },
+                Instruction::Operate(direction, offset, operation) => {
+                    tape.operate(self.head, *direction, Some(*offset), *operation, mode)
+                }
+                Instruction::Branch(target) => {
+                    if tape.get(self.head).is_zero() {
+                        next = *target;
+                    }
+                    Ok(())
+                }
+                Instruction::Repeat(target) => {
+                    if counts {
+                        next = *target;
+                    }
+                    Ok(())
+                }
+                Instruction::Jump(target) => {
+                    next = *target;
+                    Ok(())
+                }
+                Instruction::Nop => Ok(()),
+                Instruction::Define { name, function } => {
+                    let (name, function) = (*name, *function);
+                    self.define(function);
+                    self.names[name] = Some(function);
+                    Ok(())
+                }
+                Instruction::Call(name) => {
+                    let Some(function) = self.names[*name] else {
+                        let name = self.program.names[*name].clone();
+                        return Err(self.fail(RuntimeErrorKind::UnknownFunction(name), tape));
+                    };
+                    next = self.call(function, tape)?;
+                    Ok(())
+                }
+                Instruction::GetFunction(name) => {
+                    let index = self.names[*name].and_then(|function| self.indices[function]);
+                    let Some(index) = index else {
+                        let name = self.program.names[*name].clone();
+                        return Err(self.fail(RuntimeErrorKind::UnknownFunction(name), tape));
+                    };
+                    tape.set(self.head, T::from(index as i64))
+                }
+                Instruction::CallIndirect => {
+                    let cell = tape.get(self.head);
+                    let index = cell.to_u64() as usize;
+                    if index >= self.defined.len() || T::from(index as i64) != cell {
+                        return Err(self.fail(RuntimeErrorKind::InvalidFunctionIndex(cell.to_string()), tape));
+                    }
+                    next = self.call(self.defined[index], tape)?;
+                    Ok(())
+                }
+                Instruction::Return => {
+                    let call = self.calls.pop().expect("a function returns to its caller");
+                    next = call.from + 1;
+                    Ok(())
+                }
+                Instruction::OutputNumber => {
+                    let value = tape.get(self.head);
+                    io.write_number(&value).map_err(|error| self.fail(error.into(), tape))?;
+                    Ok(())
+                }
+                Instruction::OutputChar => {
+                    let c = tape.get(self.head).to_char().unwrap_or(' ');
+                    io.write_char(c).map_err(|error| self.fail(error.into(), tape))?;
+                    Ok(())
+                }
+                Instruction::ReadKey => {
+                    let c = io.flush().and_then(|_| io.read_char()).map_err(|error| self.fail(error.into(), tape))?;
+                    let value = c.map_or(self.config.eof_value, T::from_char);
+                    tape.set(self.head, value)
+                }
+                Instruction::Halt => return Ok(()),
+            };
+            if let Err(error) = result {
+                return Err(self.fail(error.into(), tape));
+            }
+            self.pc = next;
+        }
+    }
+
+    /// Gives a function the next index the first time its `fun` is reached or hoisted.
+    fn define(&mut self, function: usize) {
+        if self.indices[function].is_none() {
+            self.indices[function] = Some(self.defined.len());
+            self.defined.push(function);
+        }
+    }
+
+    /// Pushes a call from the current instruction, returning the function's entry.
+    fn call(&mut self, function: usize, tape: &dyn Tape<T>) -> Result<usize, RuntimeError<T>> {
+        if self.calls.len() >= self.config.max_call_depth {
+            return Err(self.fail(RuntimeErrorKind::StackOverflow(self.config.max_call_depth), tape));
+        }
+        self.calls.push(Call { function, from: self.pc });
+        Ok(self.program.functions[function].entry)
+    }
+
+    /// The error for the current instruction, while the tape is borrowed by [`execute`](Vm::execute).
+    fn fail(&self, kind: RuntimeErrorKind, tape: &dyn Tape<T>) -> RuntimeError<T> {
+        let (start, end) = self.program.spans[self.pc];
+        RuntimeError {
+            kind,
+            start,
+            end,
+            tape: TapeSnapshot::new(tape, self.head),
+            stack: self.call_stack(),
+        }
+    }
+
+    fn error(&self, kind: RuntimeErrorKind, pc: usize) -> RuntimeError<T> {
+        let (start, end) = self.program.spans[pc];
+        RuntimeError {
+            kind,
+            start,
+            end,
+            tape: TapeSnapshot::new(&*self.tape.borrow(), self.head),
+            stack: self.call_stack(),
+        }
+    }
+
+    /// The functions being run, innermost first, with the instructions that called them.
+    pub fn call_stack(&self) -> Vec<StackFrame> {
+        self.calls.iter().rev()
+            .map(|call| {
+                let (start, end) = self.program.spans[call.from];
+                let name = self.program.functions[call.function].name;
+                StackFrame {
+                    function: self.program.names[name].clone(),
+                    start,
+                    end,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Moves the head by `offset` cells, as every move of a [`Machine`](crate::Machine) does.
+fn move_head<T: Number>(tape: &mut dyn Tape<T>, head: &mut isize, offset: i128) -> Result<(), TapeError> {
+    let index = offset_index(*head, offset)?;
+    match tape.reach(index) {
+        Ok(()) => *head = index,
+        Err(TapeError::Underflow) => *head = 0,
+        Err(error) => return Err(error),
+    }
+    Ok(())
+}
+
+/// Runs an [`Add`](Instruction::Add) or [`Subtract`](Instruction::Subtract), returning whether it changed the cell.
+///
+/// The commands it stands for fail if their first change needs room the tape does not have, so it leaves a zero cell
+/// alone unless a nonzero value fits.
+fn add<T: Number>(tape: &mut dyn Tape<T>, head: isize, operation: Operation, value: T, mode: OverflowMode) -> Result<bool, TapeError> {
+    let cell = tape.get(head);
+    let value = cell.apply(operation, value, mode).ok_or(TapeError::Overflow)?;
+    if cell.is_zero() && tape.set(head, T::from(1)).is_err() {
+        return Ok(false);
+    }
+    tape.set(head, value)?;
+    Ok(true)
+}
+
+/// Runs a [`Transfer`](Instruction::Transfer), leaving the tape as it was if a target is out of reach or has no room.
+fn transfer<T: Number>(tape: &mut dyn Tape<T>, head: isize, targets: &[(isize, T)]) -> Result<(), TapeError> {
+    let count = tape.get(head);
+    if count.is_zero() {
+        return Ok(());
+    }
+    for (offset, _) in targets {
+        match head.checked_add(*offset) {
+            Some(target) if tape.reach(target).is_ok() => {}
+            _ => return Ok(()),
+        }
+    }
+    let mut previous = Vec::with_capacity(targets.len());
+    for (offset, factor) in targets {
+        let target = head + offset;
+        let old = tape.get(target);
+        let value = old.wrapping(Operation::Add, factor.wrapping(Operation::Multiply, count));
+        if tape.set(target, value).is_err() {
+            // Cells going back to zero make room for the others first.
+            previous.sort_by_key(|(_, old): &(isize, T)| old.is_nonzero());
+            for (target, old) in previous {
+                tape.set(target, old).expect("a cell has room for the value it held");
+            }
+            return Ok(());
+        }
+        previous.push((target, old));
+    }
+    tape.set(head, T::from(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bytecode::compile;
+    use crate::io::MemoryIo;
+    use crate::testing::{assert_same, infinite_tape, sparse_tape};
+    use turing_definitions::parser::parse;
+
+    const PROGRAMS: &[&str] = &[
+        r#""Hi\n" <2) , > , > , 42 ."#,
+        "? , ? . ? .",
+        "[5] (3> [7] <2) . > . > .",
+        r#"["ab"] < < , > ,"#,
+        "3 <| 2 |> .",
+        "[-2] |> 5 . [-3] <| .",
+        "5 + + - . 3 <+ . 2 +> . 4 <* . 2 <% . 3 </ .",
+        "5 2 <2- . 1 >3+ .",
+        "if 1 . else 2 . end 0 if 3 . else 4 . end if 5 . end",
+        "5 while . - end 3 loop . - end loop end",
+        "1 while while - end end 7 .",
+        "fun down - if down end end 900 down .",
+        "greet fun greet \"hi\" < , > , end",
+        "fun a 1 . end fun b 2 . end getfun b call getfun a call getfun a + call",
+        "fun apply call end fun later 7 . end getfun later apply",
+        "f fun f 2 . end f fun f 3 . end getfun f f",
+        "3 loop fun inner 9 . end - end inner getfun inner .",
+        "fun outer 1 inner end fun inner 0 <% end outer",
+        "fun forever forever end forever",
+        "fun a end 1 call",
+        "2 if missing end",
+        "9 < < < (2> 1 .",
+        "# a comment\n1 .",
+    ];
+
+    #[test]
+    fn test_same_as_machine() {
+        for program in PROGRAMS {
+            assert_same(program, "xy", Config::default(), infinite_tape::<i64>, false);
+        }
+    }
+
+    #[test]
+    fn test_same_as_machine_with_limits() {
+        for program in PROGRAMS {
+            for max_steps in [0, 1, 5, 20] {
+                let config = Config { max_steps: Some(max_steps), ..Config::default() };
+                assert_same(program, "", config, infinite_tape::<i64>, false);
+            }
+            let config = Config { max_call_depth: 5, eof_value: -1, ..Config::default() };
+            assert_same(program, "", config, infinite_tape::<i64>, false);
+        }
+    }
+
+    /// Programs that take the head to the ends of a sparse tape, and one step past them.
+    const AT_TAPE_ENDS: &[&str] = &[
+        "[9223372036854775807] |> 5 .",
+        "[9223372036854775807] |> >",
+        "[9223372036854775807] |> (2>",
+        "[9223372036854775807] |> 1.5",
+        r#"[9223372036854775807] |> "a""#,
+        r#"[9223372036854775807] |> ["a"]"#,
+        "[9223372036854775807] |> [1] |>",
+        "[9223372036854775807] |> [-1] <|",
+        "[9223372036854775807] |> +>",
+        "[9223372036854775807] |> [0] %>",
+        "[9223372036854775807] |> [0] /2>",
+        "[-9223372036854775808] |> [5] .",
+        "[-9223372036854775808] |> <",
+        "[-9223372036854775808] |> <2)",
+        "[-9223372036854775808] |> [1] <|",
+        "[-9223372036854775808] |> [-1] |>",
+        "[-9223372036854775808] |> <+",
+        "[-9223372036854775808] |> <2-",
+        "[-9223372036854775808] <|",
+        "[1] <18446744073709551615) .",
+        "[1] (18446744073709551615> .",
+        "[1] +18446744073709551615> .",
+    ];
+
+    #[test]
+    fn test_same_as_machine_at_tape_ends() {
+        for program in AT_TAPE_ENDS {
+            assert_same(program, "", Config::default(), sparse_tape::<i64>, false);
+        }
+    }
+
+    #[test]
+    fn test_resume_after_step_limit() {
+        let commands = parse("5 while . - end").unwrap();
+        let config = Config { max_steps: Some(4), ..Config::default() };
+        let io = Rc::new(RefCell::new(MemoryIo::new("")));
+        let mut vm = Vm::<i64>::new(compile(&commands), config).with_io(io.clone());
+        let error = vm.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::StepLimitExceeded(4));
+        assert_eq!(io.borrow().output(), "5");
+
+        vm.config.max_steps = None;
+        vm.run().unwrap();
+        assert_eq!(io.borrow().output(), "54321");
+    }
+
+    #[test]
+    fn test_spans() {
+        let program = "fun f\n  0 <%\nend\n1 f";
+        let commands = parse(program).unwrap();
+        let compiled = compile::<i64>(&commands);
+        assert_eq!(compiled.instructions.len(), compiled.spans.len());
+
+        let mut vm = Vm::new(compiled, Config::default()).with_io(Rc::new(RefCell::new(MemoryIo::new(""))));
+        let error = vm.run().unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+        assert_eq!(&program[error.start..error.end], "<%");
+        assert_eq!(error.stack, [StackFrame { function: String::from("f"), start: 19, end: 20 }]);
+    }
+}
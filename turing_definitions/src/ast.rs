@@ -1,4 +1,4 @@
-
+use std::borrow::Cow;
 
 #[derive(Debug, PartialEq, Clone, PartialOrd)]
 pub struct SpannedCommand<'a> {
@@ -10,9 +10,11 @@ pub struct SpannedCommand<'a> {
 #[derive(Debug, PartialEq, Clone, PartialOrd)]
 pub enum Command<'a> {
     AddInteger(i64),
-    AddString(&'a str),
+    AddFloat(f64),
+    AddString(Cow<'a, str>),
     WriteInteger(i64),
-    WriteString(&'a str),
+    WriteFloat(f64),
+    WriteString(Cow<'a, str>),
     // Movement
     MoveLeft,
     MoveRight,
@@ -47,3 +49,46 @@ pub enum Command<'a> {
     ReadKey,
     Comment,
 }
+
+impl Command<'_> {
+    /// The name of the kind of command, without its operands.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Command::AddInteger(_) => "add_integer",
+            Command::AddFloat(_) => "add_float",
+            Command::AddString(_) => "add_string",
+            Command::WriteInteger(_) => "write_integer",
+            Command::WriteFloat(_) => "write_float",
+            Command::WriteString(_) => "write_string",
+            Command::MoveLeft => "move_left",
+            Command::MoveRight => "move_right",
+            Command::ReadMoveLeft => "read_move_left",
+            Command::ReadMoveRight => "read_move_right",
+            Command::MoveNLeft(_) => "move_n_left",
+            Command::MoveNRight(_) => "move_n_right",
+            Command::Increment => "increment",
+            Command::Decrement => "decrement",
+            Command::LeftAdd(_) => "left_add",
+            Command::RightAdd(_) => "right_add",
+            Command::LeftSubtract(_) => "left_subtract",
+            Command::RightSubtract(_) => "right_subtract",
+            Command::LeftMultiply(_) => "left_multiply",
+            Command::RightMultiply(_) => "right_multiply",
+            Command::LeftDivide(_) => "left_divide",
+            Command::RightDivide(_) => "right_divide",
+            Command::LeftModulo(_) => "left_modulo",
+            Command::RightModulo(_) => "right_modulo",
+            Command::If(..) => "if",
+            Command::While(_) => "while",
+            Command::Loop(_) => "loop",
+            Command::FunctionDefinition(..) => "function_definition",
+            Command::FunctionCall(_) => "function_call",
+            Command::GetFunction(_) => "get_function",
+            Command::CallFunction => "call_function",
+            Command::OutputNumber => "output_number",
+            Command::OutputChar => "output_char",
+            Command::ReadKey => "read_key",
+            Command::Comment => "comment",
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::parser::ParseError;
+
+
+/// A 1-based line and column, with the column counted in characters.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Maps byte offsets in a source file to lines and columns.
+pub struct SourceMap<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(source: &'a str) -> SourceMap<'a> {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        SourceMap {
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn location(&self, offset: usize) -> Location {
+        let offset = offset.min(self.source.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        let column = self.source[line_start..offset].chars().count() + 1;
+        Location {
+            line: line + 1,
+            column,
+        }
+    }
+
+    /// Returns the text of a 1-based line without its line ending.
+    pub fn line(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line - 1];
+        let end = self.line_starts.get(line).map(|end| end - 1).unwrap_or(self.source.len());
+        self.source[start..end].trim_end_matches('\r')
+    }
+}
+
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, start: usize, end: usize) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            label: None,
+            notes: Vec::new(),
+            start,
+            end,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Diagnostic {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders the diagnostic in the style of rustc, underlining the span on its first line.
+    pub fn render(&self, source_map: &SourceMap, file_name: &str) -> String {
+        let location = source_map.location(self.start);
+        let line = source_map.line(location.line);
+        let line_number = location.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        let prefix: String = line.chars()
+            .take(location.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = source_map.location(self.end.max(self.start));
+        let underline_len = if end.line == location.line {
+            end.column.saturating_sub(location.column).max(1)
+        } else {
+            (line.chars().count() + 1).saturating_sub(location.column).max(1)
+        };
+        let mut underline = format!("{}{}", prefix, "^".repeat(underline_len));
+        if let Some(label) = &self.label {
+            underline.push(' ');
+            underline.push_str(label);
+        }
+
+        let mut output = format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}\n",
+            self.message,
+            gutter, file_name, location.line, location.column,
+            gutter,
+            line_number, line,
+            gutter, underline,
+        );
+        for note in &self.notes {
+            output.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+        output
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Diagnostic {
+        Diagnostic::error(error.to_string(), error.start, error.end)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        let source_map = SourceMap::new("ab\ncd\n\nλx");
+        assert_eq!(source_map.location(0), Location { line: 1, column: 1 });
+        assert_eq!(source_map.location(2), Location { line: 1, column: 3 });
+        assert_eq!(source_map.location(3), Location { line: 2, column: 1 });
+        assert_eq!(source_map.location(6), Location { line: 3, column: 1 });
+        assert_eq!(source_map.location(9), Location { line: 4, column: 2 });
+    }
+
+    #[test]
+    fn test_line() {
+        let source_map = SourceMap::new("ab\r\ncd");
+        assert_eq!(source_map.line(1), "ab");
+        assert_eq!(source_map.line(2), "cd");
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "+ >\n< @@ -\n";
+        let diagnostic = Diagnostic::error("unexpected character", 6, 8).with_label("here");
+        let expected = "\
+error: unexpected character
+ --> test.txt:2:3
+  |
+2 | < @@ -
+  |   ^^ here
+";
+        assert_eq!(diagnostic.render(&SourceMap::new(source), "test.txt"), expected);
+    }
+
+    #[test]
+    fn test_render_multiline_span() {
+        let source = "while\n+\n";
+        let diagnostic = Diagnostic::error("unclosed block", 0, 8).with_note("add an `end`");
+        let expected = "\
+error: unclosed block
+ --> test.txt:1:1
+  |
+1 | while
+  | ^^^^^
+  = note: add an `end`
+";
+        assert_eq!(diagnostic.render(&SourceMap::new(source), "test.txt"), expected);
+    }
+}
@@ -1,5 +1,8 @@
 pub mod ast;
+pub mod diagnostics;
 pub mod parser;
+pub mod resolve;
+pub mod tape;
 
 #[derive(Debug, PartialEq, Copy, Clone, PartialOrd)]
 pub enum Direction {
@@ -7,26 +10,222 @@ pub enum Direction {
     Right,
 }
 
-pub trait Tape<T>: std::fmt::Debug {
-    fn get(&self, index: usize) -> T;
-    fn set(&mut self, index: usize, value: T);
-    fn add(&mut self, index: usize, direction: Direction, offset: Option<usize>);
-    fn sub(&mut self, index: usize, direction: Direction, offset: Option<usize>);
-    fn mul(&mut self, index: usize, direction: Direction, offset: Option<usize>);
-    fn div(&mut self, index: usize, direction: Direction, offset: Option<usize>);
-    fn modulo(&mut self, index: usize, direction: Direction, offset: Option<usize>);
-    fn grow(&mut self);
-    fn in_bounds(&self, index: usize) -> bool;
-    fn increment(&mut self, index: usize);
-    fn decrement(&mut self, index: usize);
-    
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+/// How arithmetic behaves when a result does not fit in a cell.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum OverflowMode {
+    /// Wrap around using two's complement, the same in every build profile.
+    #[default]
+    Wrapping,
+    /// Clamp to the smallest or largest value a cell can hold.
+    Saturating,
+    /// Stop the program with an error.
+    Checked,
+    /// Reduce every result into `0..modulus`, like the byte cells of Brainfuck when the modulus is 256.
+    ///
+    /// Every operation overflows if the modulus is 0 or larger than [`Number::max_modulus`] for the cell type, since
+    /// its results would not fit in a cell.
+    Modular(u64),
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TapeError {
+    Overflow,
+    DivisionByZero,
+    Underflow,
+    /// The tape would need more than this many cells.
+    LimitExceeded(usize),
+    /// The memory for the cells the tape would need could not be allocated.
+    OutOfMemory,
+    /// The cell would be past either end of `isize`.
+    OutOfRange,
+}
+
+/// The index `offset` cells to the right of `index`, which is out of range past either end of `isize`.
+pub fn offset_index(index: isize, offset: i128) -> Result<isize, TapeError> {
+    (index as i128).checked_add(offset)
+        .and_then(|target| isize::try_from(target).ok())
+        .ok_or(TapeError::OutOfRange)
+}
+
+pub trait Tape<T: Number>: std::fmt::Debug {
+    fn get(&self, index: isize) -> T;
+    fn set(&mut self, index: isize, value: T) -> Result<(), TapeError>;
+    /// Adds a cell to the end of the tape in `direction`.
+    fn grow(&mut self, direction: Direction) -> Result<(), TapeError>;
+    fn in_bounds(&self, index: isize) -> bool;
+    /// The number of cells held in memory.
+    fn allocated(&self) -> usize;
+    /// How many cells the tape would grow by to bring `index` in bounds, or 0 if it cannot grow that way.
+    fn cells_to_reach(&self, index: isize) -> usize;
+
+    /// Grows the tape until `index` is in bounds. Every tape contains cell 0.
+    fn reach(&mut self, index: isize) -> Result<(), TapeError> {
+        let direction = if index < 0 { Direction::Left } else { Direction::Right };
+        while !self.in_bounds(index) {
+            self.grow(direction)?;
+        }
+        Ok(())
+    }
+
+    /// Combines the cell `offset` cells away in `direction` with the cell at `index`, storing the result in the former.
+    fn operate(&mut self, index: isize, direction: Direction, offset: Option<usize>, operation: Operation, mode: OverflowMode) -> Result<(), TapeError> {
+        let offset = offset.unwrap_or(1) as i128;
+        let target = match direction {
+            Direction::Left => offset_index(index, -offset)?,
+            Direction::Right => offset_index(index, offset)?,
+        };
+        if matches!(operation, Operation::Divide | Operation::Modulo) && self.get(index).is_zero() {
+            return Err(TapeError::DivisionByZero);
+        }
+        self.reach(target)?;
+        let value = self.get(target).apply(operation, self.get(index), mode).ok_or(TapeError::Overflow)?;
+        self.set(target, value)
+    }
+
+    fn increment(&mut self, index: isize, mode: OverflowMode) -> Result<(), TapeError> {
+        let value = self.get(index).apply(Operation::Add, T::from(1), mode).ok_or(TapeError::Overflow)?;
+        self.set(index, value)
+    }
+
+    fn decrement(&mut self, index: isize, mode: OverflowMode) -> Result<(), TapeError> {
+        let value = self.get(index).apply(Operation::Subtract, T::from(1), mode).ok_or(TapeError::Overflow)?;
+        self.set(index, value)
+    }
 }
 
 pub trait Number: Copy + Clone + PartialEq + PartialOrd + std::ops::Add<Output = Self> + std::ops::Sub<Output = Self> + std::ops::Mul<Output = Self> + std::ops::Div<Output = Self> + std::ops::Rem<Output = Self> + Default + std::fmt::Debug + std::fmt::Display {
     fn is_zero(&self) -> bool;
     fn is_nonzero(&self) -> bool;
     fn from(i: i64) -> Self;
+    fn from_f64(f: f64) -> Self;
     fn to_u64(&self) -> u64;
+    /// The value without its fraction, saturating at the ends of `i128`.
+    fn to_i128(&self) -> i128;
+    fn from_char(c: char) -> Self;
+    /// The character whose code point is the cell's value, or `None` if the value is negative or not a code point.
+    fn to_char(&self) -> Option<char>;
+    fn wrapping(self, operation: Operation, rhs: Self) -> Self;
+    fn saturating(self, operation: Operation, rhs: Self) -> Self;
+    fn checked(self, operation: Operation, rhs: Self) -> Option<Self>;
+    /// Reduces the result into `0..modulus`, or returns `None` if the modulus is 0 or larger than [`Number::max_modulus`].
+    fn modular(self, operation: Operation, rhs: Self, modulus: u64) -> Option<Self>;
+    /// The largest modulus whose results `0..modulus` all fit in a cell.
+    fn max_modulus() -> u64;
+
+    /// Applies `operation` under `mode`, returning `None` if the result overflows.
+    ///
+    /// Integer division and modulo by zero panic, so callers must rule out a zero `rhs` first.
+    fn apply(self, operation: Operation, rhs: Self, mode: OverflowMode) -> Option<Self> {
+        match mode {
+            OverflowMode::Wrapping => Some(self.wrapping(operation, rhs)),
+            OverflowMode::Saturating => Some(self.saturating(operation, rhs)),
+            OverflowMode::Checked => self.checked(operation, rhs),
+            OverflowMode::Modular(modulus) => self.modular(operation, rhs, modulus),
+        }
+    }
+}
+
+macro_rules! integer_operations {
+    ($t:ty) => {
+        fn to_i128(&self) -> i128 {
+            *self as i128
+        }
+
+        fn wrapping(self, operation: Operation, rhs: Self) -> Self {
+            match operation {
+                Operation::Add => self.wrapping_add(rhs),
+                Operation::Subtract => self.wrapping_sub(rhs),
+                Operation::Multiply => self.wrapping_mul(rhs),
+                Operation::Divide => self.wrapping_div(rhs),
+                Operation::Modulo => self.wrapping_rem(rhs),
+            }
+        }
+
+        fn saturating(self, operation: Operation, rhs: Self) -> Self {
+            match operation {
+                Operation::Add => self.saturating_add(rhs),
+                Operation::Subtract => self.saturating_sub(rhs),
+                Operation::Multiply => self.saturating_mul(rhs),
+                Operation::Divide => self.saturating_div(rhs),
+                Operation::Modulo => self.checked_rem(rhs).unwrap_or(0),
+            }
+        }
+
+        fn checked(self, operation: Operation, rhs: Self) -> Option<Self> {
+            match operation {
+                Operation::Add => self.checked_add(rhs),
+                Operation::Subtract => self.checked_sub(rhs),
+                Operation::Multiply => self.checked_mul(rhs),
+                Operation::Divide => self.checked_div(rhs),
+                Operation::Modulo => self.checked_rem(rhs),
+            }
+        }
+
+        fn modular(self, operation: Operation, rhs: Self, modulus: u64) -> Option<Self> {
+            if modulus == 0 || modulus > Self::max_modulus() {
+                return None;
+            }
+            let (lhs, rhs) = (self as i128, rhs as i128);
+            let result = match operation {
+                Operation::Add => lhs + rhs,
+                Operation::Subtract => lhs - rhs,
+                Operation::Multiply => lhs * rhs,
+                Operation::Divide => lhs / rhs,
+                Operation::Modulo => lhs % rhs,
+            };
+            Some(result.rem_euclid(modulus as i128) as $t)
+        }
+
+        fn max_modulus() -> u64 {
+            <$t>::MAX as u64 + 1
+        }
+    };
+}
+
+macro_rules! float_operations {
+    ($t:ty) => {
+        fn to_i128(&self) -> i128 {
+            *self as i128
+        }
+
+        fn wrapping(self, operation: Operation, rhs: Self) -> Self {
+            match operation {
+                Operation::Add => self + rhs,
+                Operation::Subtract => self - rhs,
+                Operation::Multiply => self * rhs,
+                Operation::Divide => self / rhs,
+                Operation::Modulo => self % rhs,
+            }
+        }
+
+        fn saturating(self, operation: Operation, rhs: Self) -> Self {
+            self.wrapping(operation, rhs).clamp(<$t>::MIN, <$t>::MAX)
+        }
+
+        fn checked(self, operation: Operation, rhs: Self) -> Option<Self> {
+            Some(self.wrapping(operation, rhs)).filter(|result| result.is_finite())
+        }
+
+        fn modular(self, operation: Operation, rhs: Self, modulus: u64) -> Option<Self> {
+            if modulus == 0 {
+                return None;
+            }
+            Some(self.wrapping(operation, rhs).rem_euclid(modulus as $t))
+        }
+
+        fn max_modulus() -> u64 {
+            u64::MAX
+        }
+    };
 }
 
 impl Number for i64 {
@@ -42,9 +241,23 @@ impl Number for i64 {
         i
     }
 
+    fn from_f64(f: f64) -> Self {
+        f as i64
+    }
+
     fn to_u64(&self) -> u64 {
         *self as u64
     }
+
+    fn from_char(c: char) -> Self {
+        c as i64
+    }
+
+    fn to_char(&self) -> Option<char> {
+        u32::try_from(*self).ok().and_then(char::from_u32)
+    }
+
+    integer_operations!(i64);
 }
 impl Number for i32 {
     fn is_zero(&self) -> bool {
@@ -59,9 +272,23 @@ impl Number for i32 {
         i as i32
     }
 
+    fn from_f64(f: f64) -> Self {
+        f as i32
+    }
+
     fn to_u64(&self) -> u64 {
         *self as u64
     }
+
+    fn from_char(c: char) -> Self {
+        c as i32
+    }
+
+    fn to_char(&self) -> Option<char> {
+        u32::try_from(*self).ok().and_then(char::from_u32)
+    }
+
+    integer_operations!(i32);
 }
 impl Number for i16 {
     fn is_zero(&self) -> bool {
@@ -76,9 +303,23 @@ impl Number for i16 {
         i as i16
     }
 
+    fn from_f64(f: f64) -> Self {
+        f as i16
+    }
+
     fn to_u64(&self) -> u64 {
         *self as u64
     }
+
+    fn from_char(c: char) -> Self {
+        c as i16
+    }
+
+    fn to_char(&self) -> Option<char> {
+        u32::try_from(*self).ok().and_then(char::from_u32)
+    }
+
+    integer_operations!(i16);
 }
 impl Number for i8 {
     fn is_zero(&self) -> bool {
@@ -93,9 +334,23 @@ impl Number for i8 {
         i as i8
     }
 
+    fn from_f64(f: f64) -> Self {
+        f as i8
+    }
+
     fn to_u64(&self) -> u64 {
         *self as u64
     }
+
+    fn from_char(c: char) -> Self {
+        c as i8
+    }
+
+    fn to_char(&self) -> Option<char> {
+        u32::try_from(*self).ok().and_then(char::from_u32)
+    }
+
+    integer_operations!(i8);
 }
 impl Number for f64 {
     fn is_zero(&self) -> bool {
@@ -110,9 +365,23 @@ impl Number for f64 {
         i as f64
     }
 
+    fn from_f64(f: f64) -> Self {
+        f
+    }
+
     fn to_u64(&self) -> u64 {
         *self as u64
     }
+
+    fn from_char(c: char) -> Self {
+        c as u32 as f64
+    }
+
+    fn to_char(&self) -> Option<char> {
+        (*self >= 0.0).then(|| char::from_u32(*self as u32)).flatten()
+    }
+
+    float_operations!(f64);
 }
 impl Number for f32 {
     fn is_zero(&self) -> bool {
@@ -127,97 +396,171 @@ impl Number for f32 {
         i as f32
     }
 
+    fn from_f64(f: f64) -> Self {
+        f as f32
+    }
+
     fn to_u64(&self) -> u64 {
         *self as u64
     }
-}
 
-
-impl<T: Number> Tape<T> for Vec<T> {
-    fn get(&self, index: usize) -> T {
-        self[index]
+    fn from_char(c: char) -> Self {
+        c as u32 as f32
     }
 
-    fn set(&mut self, index: usize, value: T) {
-        self[index] = value;
+    fn to_char(&self) -> Option<char> {
+        (*self >= 0.0).then(|| char::from_u32(*self as u32)).flatten()
     }
 
-    fn add(&mut self, index: usize, direction: Direction, offset: Option<usize>) {
-        let offset = offset.unwrap_or(1);
-        match direction {
-            Direction::Left => {
-                self[index - offset] = self[index - offset] + self[index];
-            }
-            Direction::Right => {
-                while index + offset >= self.len() {
-                    self.grow();
-                }
-                self[index + offset] = self[index + offset] + self[index];
-            }
-        }
-    }
+    float_operations!(f32);
+}
 
-    fn sub(&mut self, index: usize, direction: Direction, offset: Option<usize>) {
-        let offset = offset.unwrap_or(1);
-        match direction {
-            Direction::Left => {
-                self[index - offset] = self[index - offset] - self[index];
-            }
-            Direction::Right => {
-                self[index + offset] = self[index + offset] - self[index];
-            }
-        }
+
+impl<T: Number> Tape<T> for Vec<T> {
+    fn get(&self, index: isize) -> T {
+        self[index as usize]
     }
 
-    fn mul(&mut self, index: usize, direction: Direction, offset: Option<usize>) {
-        let offset = offset.unwrap_or(1);
-        match direction {
-            Direction::Left => {
-                self[index - offset] = self[index - offset] * self[index];
-            }
-            Direction::Right => {
-                self[index + offset] = self[index + offset] * self[index];
-            }
-        }
+    fn set(&mut self, index: isize, value: T) -> Result<(), TapeError> {
+        self[index as usize] = value;
+        Ok(())
     }
 
-    fn div(&mut self, index: usize, direction: Direction, offset: Option<usize>) {
-        let offset = offset.unwrap_or(1);
+    fn grow(&mut self, direction: Direction) -> Result<(), TapeError> {
         match direction {
-            Direction::Left => {
-                self[index - offset] = self[index - offset] / self[index];
-            }
+            Direction::Left => Err(TapeError::Underflow),
             Direction::Right => {
-                self[index + offset] = self[index + offset] / self[index];
+                self.push(T::default());
+                Ok(())
             }
         }
     }
 
-    fn modulo(&mut self, index: usize, direction: Direction, offset: Option<usize>) {
-        let offset = offset.unwrap_or(1);
-        match direction {
-            Direction::Left => {
-                self[index - offset] = self[index - offset] % self[index];
-            }
-            Direction::Right => {
-                self[index + offset] = self[index + offset] % self[index];
-            }
+    /// Allocates every missing cell at once, failing rather than aborting when they do not fit in memory.
+    fn reach(&mut self, index: isize) -> Result<(), TapeError> {
+        if index < 0 {
+            return Err(TapeError::Underflow);
         }
+        let cells = self.cells_to_reach(index);
+        self.try_reserve(cells).map_err(|_| TapeError::OutOfMemory)?;
+        self.resize(self.len() + cells, T::default());
+        Ok(())
     }
 
-    fn grow(&mut self) {
-        self.push(T::default());
+    fn in_bounds(&self, index: isize) -> bool {
+        index >= 0 && (index as usize) < self.len()
     }
 
-    fn in_bounds(&self, index: usize) -> bool {
-        index < self.len()
+    fn cells_to_reach(&self, index: isize) -> usize {
+        (index.max(0) as usize + 1).saturating_sub(self.len())
     }
 
-    fn increment(&mut self, index: usize) {
-        self[index] = self[index] + T::from(1);
+    fn allocated(&self) -> usize {
+        self.len()
     }
+}
+
 
-    fn decrement(&mut self, index: usize) {
-        self[index] = self[index] - T::from(1);
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_overflow_modes() {
+        assert_eq!(127i8.apply(Operation::Add, 1, OverflowMode::Wrapping), Some(-128));
+        assert_eq!(127i8.apply(Operation::Add, 1, OverflowMode::Saturating), Some(127));
+        assert_eq!(127i8.apply(Operation::Add, 1, OverflowMode::Checked), None);
+        assert_eq!(0i64.apply(Operation::Subtract, 1, OverflowMode::Modular(256)), Some(255));
+        assert_eq!(200i64.apply(Operation::Multiply, 2, OverflowMode::Modular(256)), Some(144));
+        assert_eq!(i64::MIN.apply(Operation::Divide, -1, OverflowMode::Checked), None);
+    }
+
+    #[test]
+    fn test_max_modulus() {
+        assert_eq!(i8::max_modulus(), 128);
+        assert_eq!(127i8.apply(Operation::Add, 1, OverflowMode::Modular(128)), Some(0));
+        assert_eq!(0i8.apply(Operation::Subtract, 1, OverflowMode::Modular(128)), Some(127));
+        assert_eq!(i16::max_modulus(), 32768);
+        assert_eq!(i64::max_modulus(), 1 << 63);
+        assert_eq!(0i64.apply(Operation::Subtract, 1, OverflowMode::Modular(1 << 63)), Some(i64::MAX));
+        assert_eq!(0i8.apply(Operation::Add, 1, OverflowMode::Modular(256)), None);
+        assert_eq!(0i64.apply(Operation::Add, 1, OverflowMode::Modular(0)), None);
+        assert_eq!(0.0f64.apply(Operation::Add, 1.0, OverflowMode::Modular(0)), None);
+        assert_eq!(f32::max_modulus(), u64::MAX);
+    }
+
+    #[test]
+    fn test_float_overflow_modes() {
+        assert_eq!(f32::MAX.apply(Operation::Multiply, 2.0, OverflowMode::Saturating), Some(f32::MAX));
+        assert_eq!(f64::MAX.apply(Operation::Multiply, 2.0, OverflowMode::Checked), None);
+        assert_eq!((-1.5f64).apply(Operation::Add, 0.0, OverflowMode::Modular(4)), Some(2.5));
+    }
+
+    #[test]
+    fn test_chars() {
+        assert_eq!(i64::from_char('é'), 233);
+        assert_eq!(233i64.to_char(), Some('é'));
+        assert_eq!((-1i64).to_char(), None);
+        assert_eq!(0x1F600i32.to_char(), Some('😀'));
+        assert_eq!(i16::from_char('λ'), 0x3BB);
+        assert_eq!((0xD800u16 as i16).to_char(), None);
+        assert_eq!(i8::from_char('A'), 65);
+        assert_eq!(f64::from_char('A'), 65.0);
+        assert_eq!(65.7f64.to_char(), Some('A'));
+        assert_eq!(f32::from_char('z'), 122.0);
+    }
+
+    #[test]
+    fn test_chars_out_of_range() {
+        assert_eq!((-23i8).to_char(), None);
+        assert_eq!((-23i16).to_char(), None);
+        assert_eq!((-23i32).to_char(), None);
+        assert_eq!((-23i64).to_char(), None);
+        assert_eq!((-23.0f32).to_char(), None);
+        assert_eq!((-23.0f64).to_char(), None);
+        assert_eq!(i16::MAX.to_char(), Some('\u{7FFF}'));
+        assert_eq!(0x110000i32.to_char(), None);
+        assert_eq!(i64::MAX.to_char(), None);
+        assert_eq!(1e10f32.to_char(), None);
+        assert_eq!(f64::NAN.to_char(), None);
+    }
+
+    #[test]
+    fn test_to_i128() {
+        assert_eq!((-1i8).to_i128(), -1);
+        assert_eq!(i64::MIN.to_i128(), i64::MIN as i128);
+        assert_eq!((-2.7f64).to_i128(), -2);
+        assert_eq!(f64::MAX.to_i128(), i128::MAX);
+        assert_eq!(f32::NAN.to_i128(), 0);
+    }
+
+    #[test]
+    fn test_offset_index() {
+        assert_eq!(offset_index(5, -7), Ok(-2));
+        assert_eq!(offset_index(isize::MAX, 0), Ok(isize::MAX));
+        assert_eq!(offset_index(isize::MAX, 1), Err(TapeError::OutOfRange));
+        assert_eq!(offset_index(isize::MIN, -1), Err(TapeError::OutOfRange));
+        assert_eq!(offset_index(-1, u64::MAX as i128), Err(TapeError::OutOfRange));
+        assert_eq!(offset_index(0, i128::MIN), Err(TapeError::OutOfRange));
+    }
+
+    #[test]
+    fn test_tape_operate() {
+        let mut tape = vec![3i64, 4];
+        tape.operate(1, Direction::Left, None, Operation::Multiply, OverflowMode::Wrapping).unwrap();
+        assert_eq!(tape, vec![12, 4]);
+        tape.operate(0, Direction::Right, Some(2), Operation::Add, OverflowMode::Wrapping).unwrap();
+        assert_eq!(tape, vec![12, 4, 12]);
+        assert_eq!(tape.increment(2, OverflowMode::Checked), Ok(()));
+        assert_eq!(tape, vec![12, 4, 13]);
+    }
+
+    #[test]
+    fn test_tape_operate_errors() {
+        let mut tape = vec![3i64, 0];
+        assert_eq!(tape.operate(1, Direction::Left, None, Operation::Divide, OverflowMode::Wrapping), Err(TapeError::DivisionByZero));
+        assert_eq!(tape.operate(1, Direction::Left, None, Operation::Modulo, OverflowMode::Wrapping), Err(TapeError::DivisionByZero));
+        assert_eq!(tape.operate(0, Direction::Left, None, Operation::Add, OverflowMode::Wrapping), Err(TapeError::Underflow));
+        assert_eq!(tape, vec![3, 0]);
     }
 }
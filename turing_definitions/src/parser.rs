@@ -1,152 +1,473 @@
+use std::borrow::Cow;
+use std::num::IntErrorKind;
 use std::str::CharIndices;
 
 use crate::ast::{Command, SpannedCommand};
 
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseErrorKind {
+    UnexpectedCharacter(char),
+    Expected(char),
+    ExpectedFunctionName,
+    InvalidNumber,
+    IntegerOverflow,
+    FloatOverflow,
+    InvalidCharLiteral,
+    UnterminatedString,
+    InvalidEscape(char),
+    InvalidUnicodeEscape,
+    UnclosedBlock(&'static str),
+    UnmatchedEnd,
+    UnmatchedElse,
+    /// A call or `getfun` names a function that no `fun` defines.
+    UndefinedFunction(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character {:?}", c),
+            ParseErrorKind::Expected(c) => write!(f, "expected {:?}", c),
+            ParseErrorKind::ExpectedFunctionName => write!(f, "expected function name"),
+            ParseErrorKind::InvalidNumber => write!(f, "invalid number"),
+            ParseErrorKind::IntegerOverflow => write!(f, "integer literal does not fit in a 64-bit signed integer"),
+            ParseErrorKind::FloatOverflow => write!(f, "float literal is out of range"),
+            ParseErrorKind::InvalidCharLiteral => write!(f, "invalid character literal, expected a single character between `'`"),
+            ParseErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            ParseErrorKind::InvalidEscape(c) => write!(f, "invalid escape sequence `\\{}`", c),
+            ParseErrorKind::InvalidUnicodeEscape => write!(f, "invalid unicode escape, expected `\\u{{XXXX}}`"),
+            ParseErrorKind::UnclosedBlock(keyword) => write!(f, "unclosed `{}` block, expected `end`", keyword),
+            ParseErrorKind::UnmatchedEnd => write!(f, "`end` without a matching block"),
+            ParseErrorKind::UnmatchedElse => write!(f, "`else` outside of an `if` block"),
+            ParseErrorKind::UndefinedFunction(name) => write!(f, "undefined function `{}`", name),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+
+enum Literal {
+    Integer(i64),
+    Float(f64),
+}
+
+enum Magnitude {
+    Integer(i128),
+    Float(f64),
+}
+
+
 struct Lexer<'a> {
     input: &'a str,
     char_indices: std::iter::Peekable<CharIndices<'a>>,
-    end_stack: Vec<()>,
+    errors: Vec<ParseError>,
 }
 
 
 impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str) -> Lexer {
+    pub fn new(input: &'a str) -> Lexer<'a> {
         Lexer {
             input,
             char_indices: input.char_indices().peekable(),
-            end_stack: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
-    fn parse_if(&mut self) -> (Vec<SpannedCommand<'a>>, Option<Vec<SpannedCommand<'a>>>, usize) {
-        self.end_stack.push(());
-        let stack_len = self.end_stack.len();
-        let mut commands = Vec::new();
-        let mut else_commands = None;
-        let mut end = 0;
-        'outer: while let Some(command) = self.next() {
-            end = command.end;
-            match command.command {
-                Command::FunctionCall(name) => {
-                    if name == "else" {
-                        let mut else_commands_vec = Vec::new();
-                        while let Some(command) = self.next() {
-                            end = command.end;
-                            match command.command {
-                                Command::FunctionCall(name) => {
-                                    if name == "end" && self.end_stack.len() == stack_len {
-                                        self.end_stack.pop();
-                                        else_commands = Some(else_commands_vec);
-                                        break 'outer;
-                                    } else if name != "end" {
-                                        else_commands_vec.push(command);
-                                    }
-                                }
-                                _ => else_commands_vec.push(command),
-                            }
-                        }
-                        else_commands = Some(else_commands_vec);
-                        break;
-                    } else if name == "end" && self.end_stack.len() == stack_len {
-                        self.end_stack.pop();
-                        break;
-                    } else if name != "end" {
-                        commands.push(command);
+    fn error(&mut self, kind: ParseErrorKind, start: usize, end: usize) {
+        self.errors.push(ParseError {
+            kind,
+            start,
+            end,
+        });
+    }
+
+    /// Consumes ASCII digits and returns the end offset of the last one consumed.
+    fn consume_digits(&mut self, mut end: usize) -> usize {
+        while let Some((i, c)) = self.char_indices.peek() {
+            let i = *i;
+            let c = *c;
+            if c.is_ascii_digit() {
+                self.char_indices.next();
+                end = i + 1;
+            } else {
+                break;
+            }
+        }
+        end
+    }
+
+    /// Consumes `c` if it is the next character, returning the end offset past it.
+    fn expect(&mut self, c: char, start: usize, end: usize) -> Option<usize> {
+        match self.char_indices.peek() {
+            Some((i, found)) if *found == c => {
+                let i = *i;
+                self.char_indices.next();
+                Some(i + c.len_utf8())
+            }
+            _ => {
+                self.error(ParseErrorKind::Expected(c), start, end);
+                None
+            }
+        }
+    }
+
+    fn parse_number<N: std::str::FromStr>(&mut self, text: &str, start: usize, end: usize) -> Option<N> {
+        match text.parse() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                self.error(ParseErrorKind::InvalidNumber, start, end);
+                None
+            }
+        }
+    }
+
+    /// Parses the `digits>` suffix of a right-hand arithmetic operator such as `+3>`.
+    fn parse_right_offset(&mut self, start: usize, end: usize) -> Option<(usize, usize)> {
+        let digits_start = end;
+        let end = self.consume_digits(end);
+        let offset = self.parse_number(&self.input[digits_start..end], start, end)?;
+        let end = self.expect('>', start, end)?;
+        Some((offset, end))
+    }
+
+    /// Parses a string body after its opening quote, returning the decoded contents and the offset of the closing quote.
+    ///
+    /// Strings without escape sequences are borrowed straight from the input.
+    fn parse_string(&mut self, start: usize) -> Option<(Cow<'a, str>, usize)> {
+        let input = self.input;
+        let string_start = start + 1;
+        let mut decoded: Option<String> = None;
+        while let Some((i, c)) = self.char_indices.next() {
+            match c {
+                '"' => {
+                    let string = match decoded {
+                        Some(decoded) => Cow::Owned(decoded),
+                        None => Cow::Borrowed(&input[string_start..i]),
+                    };
+                    return Some((string, i));
+                }
+                '\\' => {
+                    let escaped = self.parse_escape(i);
+                    let decoded = decoded.get_or_insert_with(|| input[string_start..i].to_string());
+                    if let Some(escaped) = escaped {
+                        decoded.push(escaped);
+                    }
+                }
+                c => {
+                    if let Some(decoded) = decoded.as_mut() {
+                        decoded.push(c);
                     }
                 }
-                _ => commands.push(command),
             }
         }
-        (commands, else_commands, end)
+        self.error(ParseErrorKind::UnterminatedString, start, input.len());
+        None
     }
 
-    fn parse_while(&mut self) -> (Vec<SpannedCommand<'a>>, usize) {
-        self.end_stack.push(());
-        let stack_len = self.end_stack.len();
-        let mut commands = Vec::new();
-        let mut end = 0;
-        while let Some(command) = self.next() {
-            end = command.end;
-            match command.command {
-                Command::FunctionCall(name) => {
-                    if name == "end" && self.end_stack.len() == stack_len {
-                        self.end_stack.pop();
-                        break;
-                    } else if name != "end" {
-                        commands.push(command);
-                    }
+    /// Parses the escape sequence following the backslash at `start`.
+    fn parse_escape(&mut self, start: usize) -> Option<char> {
+        let (i, c) = self.char_indices.next()?;
+        let escaped = match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            'u' => return self.parse_unicode_escape(start, i + 1),
+            c => {
+                self.error(ParseErrorKind::InvalidEscape(c), start, i + c.len_utf8());
+                return None;
+            }
+        };
+        Some(escaped)
+    }
+
+    /// Parses the `{XXXX}` part of a `\u{XXXX}` escape, leaving the closing quote unconsumed on error.
+    fn parse_unicode_escape(&mut self, start: usize, mut end: usize) -> Option<char> {
+        let Some((i, '{')) = self.char_indices.peek().copied() else {
+            self.error(ParseErrorKind::InvalidUnicodeEscape, start, end);
+            return None;
+        };
+        self.char_indices.next();
+        let digits_start = i + 1;
+        end = digits_start;
+        while let Some((i, c)) = self.char_indices.peek().copied() {
+            if c.is_ascii_hexdigit() {
+                self.char_indices.next();
+                end = i + 1;
+            } else {
+                break;
+            }
+        }
+        let digits = &self.input[digits_start..end];
+        let Some((i, '}')) = self.char_indices.peek().copied() else {
+            self.error(ParseErrorKind::InvalidUnicodeEscape, start, end);
+            return None;
+        };
+        self.char_indices.next();
+        let escaped = u32::from_str_radix(digits, 16).ok()
+            .filter(|_| digits.len() <= 6)
+            .and_then(char::from_u32);
+        if escaped.is_none() {
+            self.error(ParseErrorKind::InvalidUnicodeEscape, start, i + 1);
+        }
+        escaped
+    }
+
+    /// Returns the offset of the next unconsumed character.
+    fn position(&mut self) -> usize {
+        self.char_indices.peek().map(|(i, _)| *i).unwrap_or(self.input.len())
+    }
+
+    /// Parses a number literal whose first character `first` at `start` has already been consumed.
+    ///
+    /// Literals are decimal, `0x` hexadecimal, `0b` binary or a `'c'` character, optionally negated with a leading `-`.
+    /// Decimal literals with a fractional part or an exponent are floats.
+    fn parse_number_literal(&mut self, first: char, start: usize) -> Option<(Literal, usize)> {
+        let (negative, first, literal_start) = if first == '-' {
+            match self.char_indices.next() {
+                Some((i, c)) if c.is_ascii_digit() => (true, c, i),
+                _ => {
+                    let end = self.position();
+                    self.error(ParseErrorKind::InvalidNumber, start, end);
+                    return None;
                 }
-                _ => commands.push(command),
+            }
+        } else {
+            (false, first, start)
+        };
+        let (magnitude, end) = self.parse_literal_magnitude(first, literal_start)?;
+        let literal = match magnitude {
+            Magnitude::Integer(value) => {
+                let value = if negative { -value } else { value };
+                Literal::Integer(self.check_integer(value, start, end)?)
+            }
+            Magnitude::Float(value) => Literal::Float(if negative { -value } else { value }),
+        };
+        Some((literal, end))
+    }
+
+    fn check_integer(&mut self, value: i128, start: usize, end: usize) -> Option<i64> {
+        match i64::try_from(value) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.error(ParseErrorKind::IntegerOverflow, start, end);
+                None
             }
         }
-        (commands, end)
     }
 
-    fn parse_loop(&mut self) -> (Vec<SpannedCommand<'a>>, usize) {
-        self.end_stack.push(());
-        let stack_len = self.end_stack.len();
-        let mut commands = Vec::new();
-        let mut end = 0;
-        while let Some(command) = self.next() {
-            end = command.end;
-            match command.command {
-                Command::FunctionCall(name) => {
-                    if name == "end" && self.end_stack.len() == stack_len{
-                        self.end_stack.pop();
-                        break;
-                    } else if name != "end" {
-                        commands.push(command);
-                    }
+    /// Parses the unsigned part of a number literal, see [`Lexer::parse_number_literal`].
+    fn parse_literal_magnitude(&mut self, first: char, start: usize) -> Option<(Magnitude, usize)> {
+        if first == '\'' {
+            return self.parse_char_literal(start).map(|(c, end)| (Magnitude::Integer(c as i128), end));
+        }
+        let (radix, digits_start) = match (first, self.char_indices.peek().copied()) {
+            ('0', Some((i, 'x' | 'X'))) => {
+                self.char_indices.next();
+                (16, i + 1)
+            }
+            ('0', Some((i, 'b' | 'B'))) => {
+                self.char_indices.next();
+                (2, i + 1)
+            }
+            _ => (10, start),
+        };
+        let end = if radix == 10 {
+            let digits_end = self.consume_digits(start + 1);
+            let end = self.consume_float_suffix(digits_end);
+            if end != digits_end {
+                return self.parse_float_literal(start, end);
+            }
+            end
+        } else {
+            let mut end = digits_start;
+            while let Some((i, c)) = self.char_indices.peek().copied() {
+                if c.is_ascii_alphanumeric() {
+                    self.char_indices.next();
+                    end = i + 1;
+                } else {
+                    break;
                 }
-                _ => commands.push(command),
+            }
+            end
+        };
+        match u64::from_str_radix(&self.input[digits_start..end], radix) {
+            Ok(n) => Some((Magnitude::Integer(n as i128), end)),
+            Err(error) if *error.kind() == IntErrorKind::PosOverflow => {
+                self.error(ParseErrorKind::IntegerOverflow, start, end);
+                None
+            }
+            Err(_) => {
+                self.error(ParseErrorKind::InvalidNumber, start, end);
+                None
             }
         }
-        (commands, end)
     }
 
-    fn parse_function_definition(&mut self) -> (&'a str, Vec<SpannedCommand<'a>>, usize) {
-        self.end_stack.push(());
-        let stack_len = self.end_stack.len();
-        let mut commands = Vec::new();
-        let mut end;
-        let name = match self.next() {
-            Some(command) => {
-                end = command.end;
-                match command.command {
-                    Command::FunctionCall(name) => name,
-                    _ => panic!("Expected function name"),
+    /// Consumes a `.digits` fraction and an `e[+-]digits` exponent following the digits ending at `end`.
+    ///
+    /// A `.` that is not followed by a digit is left alone, so `3.` still prints the cell after writing `3`.
+    fn consume_float_suffix(&mut self, mut end: usize) -> usize {
+        let rest = &self.input[end..];
+        if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            self.char_indices.next();
+            end = self.consume_digits(end + 1);
+        }
+        let rest = &self.input[end..];
+        if rest.starts_with(['e', 'E']) {
+            let sign = usize::from(rest[1..].starts_with(['+', '-']));
+            if rest[1 + sign..].starts_with(|c: char| c.is_ascii_digit()) {
+                for _ in 0..=sign {
+                    self.char_indices.next();
                 }
+                end = self.consume_digits(end + 1 + sign);
             }
-            None => panic!("Expected function name"),
+        }
+        end
+    }
+
+    fn parse_float_literal(&mut self, start: usize, end: usize) -> Option<(Magnitude, usize)> {
+        match self.input[start..end].parse::<f64>() {
+            Ok(value) if value.is_finite() => Some((Magnitude::Float(value), end)),
+            _ => {
+                self.error(ParseErrorKind::FloatOverflow, start, end);
+                None
+            }
+        }
+    }
+
+    /// Parses a character literal after its opening quote at `start`.
+    fn parse_char_literal(&mut self, start: usize) -> Option<(char, usize)> {
+        let c = match self.char_indices.next() {
+            Some((i, '\\')) => self.parse_escape(i),
+            Some((_, '\'')) | None => {
+                let end = self.position();
+                self.error(ParseErrorKind::InvalidCharLiteral, start, end);
+                return None;
+            }
+            Some((_, c)) => Some(c),
         };
-        while let Some(command) = self.next() {
-            end = command.end;
-            match command.command {
-                Command::FunctionCall(name) => {
-                    if name == "end" && self.end_stack.len() == stack_len {
-                        self.end_stack.pop();
+        match self.char_indices.peek().copied() {
+            Some((i, '\'')) => {
+                self.char_indices.next();
+                c.map(|c| (c, i + 1))
+            }
+            _ => {
+                let mut end = self.position();
+                while let Some((i, c)) = self.char_indices.peek().copied() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    self.char_indices.next();
+                    end = i + c.len_utf8();
+                    if c == '\'' {
                         break;
-                    } else if name != "end" {
-                        commands.push(command);
                     }
                 }
-                _ => commands.push(command),
+                self.error(ParseErrorKind::InvalidCharLiteral, start, end);
+                None
+            }
+        }
+    }
+
+    fn parse_identifier(&mut self, start: usize) -> Option<(&'a str, usize)> {
+        while let Some((_, c)) = self.char_indices.peek() {
+            if c.is_whitespace() {
+                self.char_indices.next();
+            } else {
+                break;
             }
         }
-        (name, commands, end)
+        let name_start = match self.char_indices.peek() {
+            Some((i, c)) if c.is_alphabetic() || *c == '_' => *i,
+            Some((i, c)) => {
+                let (i, c) = (*i, *c);
+                self.error(ParseErrorKind::ExpectedFunctionName, i, i + c.len_utf8());
+                return None;
+            }
+            None => {
+                self.error(ParseErrorKind::ExpectedFunctionName, start, self.input.len());
+                return None;
+            }
+        };
+        let mut end = name_start;
+        while let Some((i, c)) = self.char_indices.peek() {
+            let i = *i;
+            let c = *c;
+            if c.is_alphabetic() || c == '_' {
+                self.char_indices.next();
+                end = i + c.len_utf8();
+            } else {
+                break;
+            }
+        }
+        Some((&self.input[name_start..end], end))
     }
 
-    fn parse_get_function(&mut self) -> (&'a str, usize) {
-        match self.next() {
-            Some(command) => {
-                match command.command {
-                    Command::FunctionCall(name) => (name, command.end),
-                    _ => panic!("Expected function name"),
+    /// Parses commands up to the `end` closing the block opened by `keyword` at `start`.
+    ///
+    /// An `else` splits the block in two when `allow_else` is set and is reported as an error otherwise.
+    fn parse_block(&mut self, keyword: &'static str, start: usize, mut end: usize, allow_else: bool) -> (Vec<SpannedCommand<'a>>, Option<Vec<SpannedCommand<'a>>>, usize) {
+        let mut commands = Vec::new();
+        let mut else_commands: Option<Vec<SpannedCommand<'a>>> = None;
+        while let Some(command) = self.next() {
+            end = command.end;
+            match command.command {
+                Command::FunctionCall("end") => {
+                    return (commands, else_commands, end);
+                }
+                Command::FunctionCall("else") if allow_else && else_commands.is_none() => {
+                    else_commands = Some(Vec::new());
+                }
+                Command::FunctionCall("else") => {
+                    self.error(ParseErrorKind::UnmatchedElse, command.start, command.end);
                 }
+                _ => match else_commands.as_mut() {
+                    Some(else_commands) => else_commands.push(command),
+                    None => commands.push(command),
+                },
             }
-            None => panic!("Expected function name"),
         }
+        self.error(ParseErrorKind::UnclosedBlock(keyword), start, start + keyword.len());
+        (commands, else_commands, end)
+    }
+
+    fn parse_if(&mut self, start: usize, end: usize) -> (Vec<SpannedCommand<'a>>, Option<Vec<SpannedCommand<'a>>>, usize) {
+        self.parse_block("if", start, end, true)
+    }
+
+    fn parse_while(&mut self, start: usize, end: usize) -> (Vec<SpannedCommand<'a>>, usize) {
+        let (commands, _, end) = self.parse_block("while", start, end, false);
+        (commands, end)
+    }
+
+    fn parse_loop(&mut self, start: usize, end: usize) -> (Vec<SpannedCommand<'a>>, usize) {
+        let (commands, _, end) = self.parse_block("loop", start, end, false);
+        (commands, end)
+    }
+
+    fn parse_function_definition(&mut self, start: usize, end: usize) -> Option<(&'a str, Vec<SpannedCommand<'a>>, usize)> {
+        let name = self.parse_identifier(start);
+        let body_start = name.map(|(_, name_end)| name_end).unwrap_or(end);
+        let (commands, _, end) = self.parse_block("fun", start, body_start, false);
+        let (name, _) = name?;
+        Some((name, commands, end))
+    }
+
+    fn parse_get_function(&mut self, start: usize) -> Option<(&'a str, usize)> {
+        self.parse_identifier(start)
     }
 }
 
@@ -176,7 +497,7 @@ impl<'a> Iterator for Lexer<'a> {
                         let command = &self.input[start..end];
                         match command {
                             "if" => {
-                                let (then_commands, else_commands, end) = self.parse_if();
+                                let (then_commands, else_commands, end) = self.parse_if(start, end);
                                 SpannedCommand {
                                     command: Command::If(then_commands, else_commands),
                                     start,
@@ -184,7 +505,7 @@ impl<'a> Iterator for Lexer<'a> {
                                 }
                             }
                             "while" => {
-                                let (commands, end) = self.parse_while();
+                                let (commands, end) = self.parse_while(start, end);
                                 SpannedCommand {
                                     command: Command::While(commands),
                                     start,
@@ -192,7 +513,7 @@ impl<'a> Iterator for Lexer<'a> {
                                 }
                             }
                             "loop" => {
-                                let (commands, end) = self.parse_loop();
+                                let (commands, end) = self.parse_loop(start, end);
                                 SpannedCommand {
                                     command: Command::Loop(commands),
                                     start,
@@ -200,7 +521,9 @@ impl<'a> Iterator for Lexer<'a> {
                                 }
                             }
                             "fun" => {
-                                let (name, commands, end) = self.parse_function_definition();
+                                let Some((name, commands, end)) = self.parse_function_definition(start, end) else {
+                                    return self.next();
+                                };
                                 SpannedCommand {
                                     command: Command::FunctionDefinition(name, commands),
                                     start,
@@ -208,7 +531,9 @@ impl<'a> Iterator for Lexer<'a> {
                                 }
                             }
                             "getfun" => {
-                                let (name, end) = self.parse_get_function();
+                                let Some((name, end)) = self.parse_get_function(start) else {
+                                    return self.next();
+                                };
                                 SpannedCommand {
                                     command: Command::GetFunction(name),
                                     start,
@@ -231,364 +556,164 @@ impl<'a> Iterator for Lexer<'a> {
                             }
                         }
                     }
-                    '0'..='9' => {
-                        while let Some((i, c)) = self.char_indices.peek() {
-                            let i = *i;
-                            let c = *c;
-                            if c.is_numeric() {
+                    '0'..='9' | '\'' => {
+                        let Some((literal, end)) = self.parse_number_literal(c, start) else {
+                            return self.next();
+                        };
+                        SpannedCommand {
+                            command: match literal {
+                                Literal::Integer(i) => Command::AddInteger(i),
+                                Literal::Float(f) => Command::AddFloat(f),
+                            },
+                            start,
+                            end,
+                        }
+                    }
+                    '-' if self.char_indices.peek().is_some_and(|(_, c)| c.is_ascii_digit()) => {
+                        let Some((digits_start, first)) = self.char_indices.next() else {
+                            return self.next();
+                        };
+                        let Some((magnitude, magnitude_end)) = self.parse_literal_magnitude(first, digits_start) else {
+                            return self.next();
+                        };
+                        end = magnitude_end;
+                        let command = match (magnitude, self.char_indices.peek().copied()) {
+                            (Magnitude::Integer(magnitude), Some((i, '>'))) => {
                                 self.char_indices.next();
-                                end = i + c.len_utf8();
-                            } else {
-                                break;
+                                let literal = &self.input[digits_start..magnitude_end];
+                                if let Some(radix) = literal.find(|c: char| !c.is_ascii_digit()) {
+                                    self.error(ParseErrorKind::Expected('>'), start, digits_start + radix);
+                                    return self.next();
+                                }
+                                end = i + 1;
+                                let Ok(offset) = usize::try_from(magnitude) else {
+                                    self.error(ParseErrorKind::IntegerOverflow, start, end);
+                                    return self.next();
+                                };
+                                Command::RightSubtract(Some(offset))
                             }
-                        }
-                        let command = &self.input[start..end];
+                            (Magnitude::Integer(magnitude), _) => {
+                                let Some(number) = self.check_integer(-magnitude, start, end) else {
+                                    return self.next();
+                                };
+                                Command::AddInteger(number)
+                            }
+                            (Magnitude::Float(magnitude), _) => Command::AddFloat(-magnitude),
+                        };
                         SpannedCommand {
-                            command: Command::AddInteger(command.parse().unwrap()),
+                            command,
                             start,
                             end,
                         }
                     }
                     '"' => {
-                        let mut found_backslash = false;
-                        while let Some((i, c)) = self.char_indices.next() {
-                            if c == '"' && !found_backslash {
-                                end = i;
-                                break;
-                            } else if c == '\\' {
-                                found_backslash = true;
-                            } else if found_backslash {
-                                found_backslash = false;
-                            } else {
-                                found_backslash = false;
-                            }
-                        }
-                        let acc = &self.input[start + 1..end];
+                        let Some((acc, end)) = self.parse_string(start) else {
+                            return self.next();
+                        };
                         SpannedCommand {
                             command: Command::AddString(acc),
                             start,
                             end,
                         }
                     }
-                    '+' => {
-                        if let Some((i, '>')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::RightAdd(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((_, c)) = self.char_indices.peek() {
-                            let c = *c;
-                            if c.is_numeric() {
-                                let mut acc = String::new();
-                                while let Some((i, c)) = self.char_indices.peek() {
-                                    let i = *i;
-                                    let c = *c;
-                                    if c == '>' {
-                                        self.char_indices.next();
-                                        end = i + 1;
-                                        break;
-                                    } else if c.is_numeric() {
-                                        acc.push(c);
-                                        self.char_indices.next();
-                                        end = i + 1;
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                SpannedCommand {
-                                    command: Command::RightAdd(Some(acc.parse().unwrap())),
-                                    start,
-                                    end,
-                                }
-                            } else {
-                                SpannedCommand {
-                                    command: Command::Increment,
-                                    start,
-                                    end,
-                                }
-                            }
-                        } else {
-                            SpannedCommand {
-                                command: Command::Increment,
-                                start,
-                                end,
-                            }
-                        }
-                    }
-                    '-' => {
-                        if let Some((i, '>')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::RightSubtract(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((_, c)) = self.char_indices.peek() {
-                            let c = *c;
-                            if c.is_numeric() {
-                                let mut acc = String::new();
-                                while let Some((i, c)) = self.char_indices.peek() {
-                                    let i = *i;
-                                    let c = *c;
-                                    if c == '>' {
-                                        self.char_indices.next();
-                                        end = i + 1;
-                                        break;
-                                    } else {
-                                        acc.push(c);
-                                        self.char_indices.next();
-                                        end = i + 1;
-                                    }
-                                }
-                                SpannedCommand {
-                                    command: Command::RightSubtract(Some(acc.parse().unwrap())),
-                                    start,
-                                    end,
-                                }
-                            } else {
-                                SpannedCommand {
-                                    command: Command::Decrement,
-                                    start,
-                                    end,
-                                }
-                            }   
-                        } else {
-                            SpannedCommand {
-                                command: Command::Decrement,
-                                start,
-                                end,
-                            }
-                        }
-                    }
-                    '*' => {
-                        if let Some((i, '>')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::RightMultiply(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((_, _)) = self.char_indices.peek() {
-                            let mut acc = String::new();
-                            while let Some((i, c)) = self.char_indices.peek() {
-                                let i = *i;
-                                let c = *c;
-                                if c == '>' {
-                                    self.char_indices.next();
-                                    end = i + 1;
-                                    break;
-                                } else {
-                                    acc.push(c);
-                                    self.char_indices.next();
-                                    end = i + 1;
+                    '+' | '-' | '*' | '/' | '%' => {
+                        let operator = c;
+                        let command = match self.char_indices.peek() {
+                            Some((i, '>')) => {
+                                end = *i + 1;
+                                self.char_indices.next();
+                                match operator {
+                                    '+' => Command::RightAdd(None),
+                                    '-' => Command::RightSubtract(None),
+                                    '*' => Command::RightMultiply(None),
+                                    '/' => Command::RightDivide(None),
+                                    _ => Command::RightModulo(None),
                                 }
                             }
-                            SpannedCommand {
-                                command: Command::RightMultiply(Some(acc.parse().unwrap())),
-                                start,
-                                end,
-                            }
-                        } else {
-                            SpannedCommand {
-                                command: Command::LeftMultiply(None),
-                                start,
-                                end,
-                            }
-                        }
-                    }
-                    '/' => {
-                        if let Some((i, '>')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::RightDivide(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((_, _)) = self.char_indices.peek() {
-                            let mut acc = String::new();
-                            while let Some((i, c)) = self.char_indices.peek() {
-                                let i = *i;
-                                let c = *c;
-                                if c == '>' {
-                                    self.char_indices.next();
-                                    end = i + 1;
-                                    break;
-                                } else {
-                                    acc.push(c);
-                                    self.char_indices.next();
-                                    end = i + 1;
+                            Some((_, c)) if c.is_ascii_digit() => {
+                                let Some((offset, offset_end)) = self.parse_right_offset(start, end) else {
+                                    return self.next();
+                                };
+                                end = offset_end;
+                                match operator {
+                                    '+' => Command::RightAdd(Some(offset)),
+                                    '-' => Command::RightSubtract(Some(offset)),
+                                    '*' => Command::RightMultiply(Some(offset)),
+                                    '/' => Command::RightDivide(Some(offset)),
+                                    _ => Command::RightModulo(Some(offset)),
                                 }
                             }
-                            SpannedCommand {
-                                command: Command::RightDivide(Some(acc.parse().unwrap())),
-                                start,
-                                end,
-                            }
-                        } else {
-                            SpannedCommand {
-                                command: Command::LeftDivide(None),
-                                start,
-                                end,
-                            }
-                        }
-                    }
-                    '%' => {
-                        if let Some((i, '>')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::RightModulo(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((_, _)) = self.char_indices.peek() {
-                            let mut acc = String::new();
-                            while let Some((i, c)) = self.char_indices.peek() {
-                                let i = *i;
-                                let c = *c;
-                                if c == '>' {
-                                    self.char_indices.next();
-                                    end = i + 1;
-                                    break;
-                                } else {
-                                    acc.push(c);
-                                    self.char_indices.next();
-                                    end = i + 1;
+                            _ => match operator {
+                                '+' => Command::Increment,
+                                '-' => Command::Decrement,
+                                _ => {
+                                    self.error(ParseErrorKind::Expected('>'), start, end);
+                                    return self.next();
                                 }
-                            }
-                            SpannedCommand {
-                                command: Command::RightModulo(Some(acc.parse().unwrap())),
-                                start,
-                                end,
-                            }
-                        } else {
-                            SpannedCommand {
-                                command: Command::LeftModulo(None),
-                                start,
-                                end,
-                            }
+                            },
+                        };
+                        SpannedCommand {
+                            command,
+                            start,
+                            end,
                         }
                     }
                     '<' => {
-                        if let Some((i, '+')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::LeftAdd(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((i, '-')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::LeftSubtract(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((i, '*')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::LeftMultiply(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((i, '/')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::LeftDivide(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((i, '%')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::LeftModulo(None),
-                                start,
-                                end,
-                            }
-                        } else if let Some((i, '|')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::ReadMoveLeft,
-                                start,
-                                end,
-                            }
-                        } else if let Some((_, c)) = self.char_indices.peek() {
-                            let c = *c;
-                            if c.is_numeric() {
-                                let mut acc = String::new();
-                                let mut operation = "";
-                                while let Some((i, c)) = self.char_indices.peek() {
-                                    let c = *c;
-                                    let i = *i;
-                                    if c == '+' || c == '-' || c == '*' || c == '/' || c == '%' || c == ')' {
-                                        self.char_indices.next();
-                                        end = i + 1;
-                                        match c {
-                                            '+' => operation = "add",
-                                            '-' => operation = "sub",
-                                            '*' => operation = "mul",
-                                            '/' => operation = "div",
-                                            '%' => operation = "mod",
-                                            ')' => operation = "move",
-                                            _ => panic!("Invalid operation"),
-                                        }
-                                        break;
-                                    } else {
-                                        acc.push(c);
-                                        self.char_indices.next();
-                                        end = i + 1;
-                                    }
-                                }
-                                let command = match operation {
-                                    "add" => Command::LeftAdd(Some(acc.parse().unwrap())),
-                                    "sub" => Command::LeftSubtract(Some(acc.parse().unwrap())),
-                                    "mul" => Command::LeftMultiply(Some(acc.parse().unwrap())),
-                                    "div" => Command::LeftDivide(Some(acc.parse().unwrap())),
-                                    "mod" => Command::LeftModulo(Some(acc.parse().unwrap())),
-                                    "move" => Command::MoveNLeft(acc.parse().unwrap()),
-                                    _ => panic!("Invalid operation"),
-                                };
-                                SpannedCommand {
-                                    command,
-                                    start,
-                                    end,
-                                }
-                            } else {
-                                SpannedCommand {
-                                    command: Command::MoveLeft,
-                                    start,
-                                    end,
+                        let command = match self.char_indices.peek() {
+                            Some((i, c)) if matches!(c, '+' | '-' | '*' | '/' | '%' | '|') => {
+                                let c = *c;
+                                end = *i + 1;
+                                self.char_indices.next();
+                                match c {
+                                    '+' => Command::LeftAdd(None),
+                                    '-' => Command::LeftSubtract(None),
+                                    '*' => Command::LeftMultiply(None),
+                                    '/' => Command::LeftDivide(None),
+                                    '%' => Command::LeftModulo(None),
+                                    _ => Command::ReadMoveLeft,
                                 }
                             }
-                        } else {
-                            SpannedCommand {
-                                command: Command::MoveLeft,
-                                start,
-                                end,
+                            Some((_, c)) if c.is_ascii_digit() => {
+                                let digits_start = end;
+                                end = self.consume_digits(end);
+                                let digits_end = end;
+                                let operation = match self.char_indices.peek() {
+                                    Some((i, c)) if matches!(c, '+' | '-' | '*' | '/' | '%' | ')') => {
+                                        let c = *c;
+                                        end = *i + 1;
+                                        self.char_indices.next();
+                                        c
+                                    }
+                                    _ => {
+                                        self.error(ParseErrorKind::Expected(')'), start, end);
+                                        return self.next();
+                                    }
+                                };
+                                let digits = &self.input[digits_start..digits_end];
+                                if operation == ')' {
+                                    let Some(n) = self.parse_number(digits, start, end) else {
+                                        return self.next();
+                                    };
+                                    Command::MoveNLeft(n)
+                                } else {
+                                    let Some(n) = self.parse_number(digits, start, end) else {
+                                        return self.next();
+                                    };
+                                    match operation {
+                                        '+' => Command::LeftAdd(Some(n)),
+                                        '-' => Command::LeftSubtract(Some(n)),
+                                        '*' => Command::LeftMultiply(Some(n)),
+                                        '/' => Command::LeftDivide(Some(n)),
+                                        _ => Command::LeftModulo(Some(n)),
+                                    }
+                                }
                             }
+                            _ => Command::MoveLeft,
+                        };
+                        SpannedCommand {
+                            command,
+                            start,
+                            end,
                         }
                     }
                     '>' => SpannedCommand {
@@ -597,36 +722,25 @@ impl<'a> Iterator for Lexer<'a> {
                         end,
                     },
                     '|' => {
-                        if let Some((i, '>')) = self.char_indices.peek() {
-                            let i = *i;
-                            self.char_indices.next();
-                            end = i + 1;
-                            SpannedCommand {
-                                command: Command::ReadMoveRight,
-                                start,
-                                end,
-                            }
-                        } else {
-                            panic!("Expected '>' after '|'");
+                        let Some(end) = self.expect('>', start, end) else {
+                            return self.next();
+                        };
+                        SpannedCommand {
+                            command: Command::ReadMoveRight,
+                            start,
+                            end,
                         }
                     }
                     '(' => {
-                        let mut acc = String::new();
-                        while let Some((i, c)) = self.char_indices.peek() {
-                            let c = *c;
-                            let i = *i;
-                            if c == '>' {
-                                self.char_indices.next();
-                                end = i + 1;
-                                break;
-                            } else {
-                                acc.push(c);
-                                self.char_indices.next();
-                                end = i + 1;
-                            }
-                        }
+                        end = self.consume_digits(end);
+                        let Some(n) = self.parse_number(&self.input[start + 1..end], start, end) else {
+                            return self.next();
+                        };
+                        let Some(end) = self.expect('>', start, end) else {
+                            return self.next();
+                        };
                         SpannedCommand {
-                            command: Command::MoveNRight(acc.parse().unwrap()),
+                            command: Command::MoveNRight(n),
                             start,
                             end,
                         }
@@ -648,50 +762,56 @@ impl<'a> Iterator for Lexer<'a> {
                     },
                     '[' => {
                         if let Some((i, '"')) = self.char_indices.peek() {
-                            let i = *i;
-                            let string_start = i;
+                            let string_start = *i;
                             self.char_indices.next();
-                            end = i + 1;
-                            let mut found_backslash = false;
-                            while let Some((i, c)) = self.char_indices.next() {
-                                if c == '"' && !found_backslash {
-                                    end = i;
-                                    break;
-                                } else if c == '\\' {
-                                    found_backslash = true;
-                                } else if found_backslash {
-                                    found_backslash = false;
-                                } else {
-                                    found_backslash = false;
-                                }
-                            }
-                            if let Some((end_i, ']')) = self.char_indices.next() {
-                                let acc = &self.input[string_start + 1..end];
-                                SpannedCommand {
-                                    command: Command::WriteString(acc),
-                                    start,
-                                    end: end_i + 1,
-                                }
-                            } else {
-                                panic!("Expected ']' after string");
+                            let Some((acc, string_end)) = self.parse_string(string_start) else {
+                                return self.next();
+                            };
+                            let Some(end) = self.expect(']', start, string_end + 1) else {
+                                return self.next();
+                            };
+                            SpannedCommand {
+                                command: Command::WriteString(acc),
+                                start,
+                                end,
                             }
                         } else {
-                            let mut acc = String::new();
-                            while let Some((i, c)) = self.char_indices.peek() {
-                                let c = *c;
-                                let i = *i;
-                                if c == ']' {
+                            let errors = self.errors.len();
+                            let literal = match self.char_indices.peek().copied() {
+                                Some((i, c)) if c.is_ascii_digit() || c == '-' || c == '\'' => {
                                     self.char_indices.next();
-                                    end = i + 1;
-                                    break;
-                                } else {
-                                    acc.push(c);
+                                    self.parse_number_literal(c, i)
+                                }
+                                _ => None,
+                            };
+                            let literal = match (literal, self.char_indices.peek().copied()) {
+                                (Some((literal, _)), Some((i, ']'))) => {
                                     self.char_indices.next();
                                     end = i + 1;
+                                    literal
                                 }
-                            }
+                                _ => {
+                                    let mut closed = false;
+                                    for (i, c) in self.char_indices.by_ref() {
+                                        end = i + c.len_utf8();
+                                        if c == ']' {
+                                            closed = true;
+                                            break;
+                                        }
+                                    }
+                                    if !closed {
+                                        self.error(ParseErrorKind::Expected(']'), start, end);
+                                    } else if self.errors.len() == errors {
+                                        self.error(ParseErrorKind::InvalidNumber, start, end);
+                                    }
+                                    return self.next();
+                                }
+                            };
                             SpannedCommand {
-                                command: Command::WriteInteger(acc.parse().unwrap()),
+                                command: match literal {
+                                    Literal::Integer(i) => Command::WriteInteger(i),
+                                    Literal::Float(f) => Command::WriteFloat(f),
+                                },
                                 start,
                                 end,
                             }
@@ -707,7 +827,7 @@ impl<'a> Iterator for Lexer<'a> {
                                 break;
                             } else {
                                 self.char_indices.next();
-                                end = i + 1;
+                                end = i + c.len_utf8();
                             }
                         }
                         SpannedCommand {
@@ -719,17 +839,18 @@ impl<'a> Iterator for Lexer<'a> {
                     c if c.is_whitespace() => {
                         while let Some((_, c)) = self.char_indices.peek() {
                             let c = *c;
-                            //let i = *i;
                             if c.is_whitespace() {
                                 self.char_indices.next();
-                                //end = i + 1;
                             } else {
                                 break;
                             }
                         }
                         return self.next();
                     }
-                    _ => panic!("Unexpected character"),
+                    c => {
+                        self.error(ParseErrorKind::UnexpectedCharacter(c), start, end);
+                        return self.next();
+                    }
                 }
             }
             None => return None,
@@ -739,8 +860,22 @@ impl<'a> Iterator for Lexer<'a> {
 }
 
 
-pub fn parse<'a>(input: &'a str) -> Vec<SpannedCommand<'a>> {
-    Lexer::new(input).collect()
+/// Parses a program, collecting every error encountered rather than stopping at the first.
+pub fn parse(input: &str) -> Result<Vec<SpannedCommand<'_>>, Vec<ParseError>> {
+    let mut lexer = Lexer::new(input);
+    let mut commands = Vec::new();
+    while let Some(command) = lexer.next() {
+        match command.command {
+            Command::FunctionCall("end") => lexer.error(ParseErrorKind::UnmatchedEnd, command.start, command.end),
+            Command::FunctionCall("else") => lexer.error(ParseErrorKind::UnmatchedElse, command.start, command.end),
+            _ => commands.push(command),
+        }
+    }
+    if lexer.errors.is_empty() {
+        Ok(commands)
+    } else {
+        Err(lexer.errors)
+    }
 }
 
 
@@ -759,7 +894,7 @@ mod test {
                 end: 3,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -767,12 +902,12 @@ mod test {
         let input = "\"hello\"";
         let expected = vec![
             SpannedCommand {
-                command: Command::AddString("hello"),
+                command: Command::AddString(Cow::Borrowed("hello")),
                 start: 0,
                 end: 6,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -785,7 +920,7 @@ mod test {
                 end: 5,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -793,12 +928,12 @@ mod test {
         let input = "[\"hello\"]";
         let expected = vec![
             SpannedCommand {
-                command: Command::WriteString("hello"),
+                command: Command::WriteString(Cow::Borrowed("hello")),
                 start: 0,
                 end: 9,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
     
     #[test]
@@ -815,7 +950,7 @@ mod test {
                 end: 18,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -825,11 +960,19 @@ mod test {
             SpannedCommand {
                 command: Command::If(
                     vec![SpannedCommand {
+                        command: Command::FunctionCall("add_one"),
+                        start: 3,
+                        end: 10,
+                    }, SpannedCommand {
                         command: Command::RightAdd(None),
                         start: 11,
                         end: 13,
                     }],
                     Some(vec![SpannedCommand {
+                        command: Command::FunctionCall("add_two"),
+                        start: 19,
+                        end: 26,
+                    }, SpannedCommand {
                         command: Command::RightSubtract(None),
                         start: 27,
                         end: 29,
@@ -839,7 +982,7 @@ mod test {
                 end: 33,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -849,6 +992,10 @@ mod test {
             SpannedCommand {
                 command: Command::If(
                     vec![SpannedCommand {
+                        command: Command::FunctionCall("add_one"),
+                        start: 3,
+                        end: 10,
+                    }, SpannedCommand {
                         command: Command::RightAdd(None),
                         start: 11,
                         end: 13,
@@ -859,7 +1006,7 @@ mod test {
                 end: 17,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -868,6 +1015,10 @@ mod test {
         let expected = vec![
             SpannedCommand {
                 command: Command::While(vec![SpannedCommand {
+                    command: Command::FunctionCall("add_one"),
+                    start: 6,
+                    end: 13,
+                }, SpannedCommand {
                     command: Command::RightAdd(None),
                     start: 14,
                     end: 16,
@@ -876,7 +1027,7 @@ mod test {
                 end: 20,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -893,7 +1044,7 @@ mod test {
                 end: 18,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -906,7 +1057,7 @@ mod test {
                 end: 14,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -919,7 +1070,7 @@ mod test {
                 end: 4,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -932,7 +1083,7 @@ mod test {
                 end: 1,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -945,7 +1096,7 @@ mod test {
                 end: 1,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -958,7 +1109,7 @@ mod test {
                 end: 1,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -971,7 +1122,7 @@ mod test {
                 end: 20,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -984,7 +1135,7 @@ mod test {
                 end: 1,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -997,7 +1148,7 @@ mod test {
                 end: 1,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1010,7 +1161,7 @@ mod test {
                 end: 2,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1023,7 +1174,7 @@ mod test {
                 end: 2,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1036,7 +1187,7 @@ mod test {
                 end: 5,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1049,7 +1200,7 @@ mod test {
                 end: 5,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1062,7 +1213,7 @@ mod test {
                 end: 1,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1075,7 +1226,7 @@ mod test {
                 end: 1,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1088,7 +1239,7 @@ mod test {
                 end: 2,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1101,7 +1252,7 @@ mod test {
                 end: 5,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
     }
 
     #[test]
@@ -1111,6 +1262,10 @@ mod test {
             SpannedCommand {
                 command: Command::If(
                     vec![SpannedCommand {
+                        command: Command::FunctionCall("add_one"),
+                        start: 3,
+                        end: 10,
+                    }, SpannedCommand {
                         command: Command::RightAdd(None),
                         start: 11,
                         end: 13,
@@ -1118,6 +1273,10 @@ mod test {
                     Some(vec![SpannedCommand {
                         command: Command::If(
                             vec![SpannedCommand {
+                                command: Command::FunctionCall("add_two"),
+                                start: 22,
+                                end: 29,
+                            }, SpannedCommand {
                                 command: Command::RightSubtract(None),
                                 start: 30,
                                 end: 32,
@@ -1125,13 +1284,382 @@ mod test {
                             None,
                         ),
                         start: 19,
-                        end: 40,
+                        end: 36,
                     }]),
                 ),
                 start: 0,
                 end: 40,
             },
         ];
-        assert_eq!(parse(input), expected);
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_unexpected_character() {
+        let input = "+ @ -";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::UnexpectedCharacter('@'),
+                start: 2,
+                end: 3,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_collects_multiple_errors() {
+        let input = "| getfun 5";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::Expected('>'),
+                start: 0,
+                end: 1,
+            },
+            ParseError {
+                kind: ParseErrorKind::ExpectedFunctionName,
+                start: 9,
+                end: 10,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_invalid_number() {
+        let input = "[abc]";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::InvalidNumber,
+                start: 0,
+                end: 5,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_unterminated_string() {
+        let input = "\"hello";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::UnterminatedString,
+                start: 0,
+                end: 6,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_unclosed_block() {
+        let input = "while - if + end";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::UnclosedBlock("while"),
+                start: 0,
+                end: 5,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_unclosed_function() {
+        let input = "fun add_one +>";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::UnclosedBlock("fun"),
+                start: 0,
+                end: 3,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_unmatched_end() {
+        let input = "loop - end end";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::UnmatchedEnd,
+                start: 11,
+                end: 14,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_unmatched_else() {
+        let input = "else while else end if + else - else end";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::UnmatchedElse,
+                start: 0,
+                end: 4,
+            },
+            ParseError {
+                kind: ParseErrorKind::UnmatchedElse,
+                start: 11,
+                end: 15,
+            },
+            ParseError {
+                kind: ParseErrorKind::UnmatchedElse,
+                start: 32,
+                end: 36,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_string_escapes() {
+        let input = r#""a\n\t\\\"\0\u{41}\u{1F600}""#;
+        let expected = vec![
+            SpannedCommand {
+                command: Command::AddString(Cow::Owned(String::from("a\n\t\\\"\0A\u{1F600}"))),
+                start: 0,
+                end: 27,
+            },
+        ];
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_write_string_escapes() {
+        let input = r#"["\"hi\""]"#;
+        let expected = vec![
+            SpannedCommand {
+                command: Command::WriteString(Cow::Owned(String::from("\"hi\""))),
+                start: 0,
+                end: 10,
+            },
+        ];
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_invalid_escape() {
+        let input = r#""a\qb" "\u{110000}" "\u41""#;
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::InvalidEscape('q'),
+                start: 2,
+                end: 4,
+            },
+            ParseError {
+                kind: ParseErrorKind::InvalidUnicodeEscape,
+                start: 8,
+                end: 18,
+            },
+            ParseError {
+                kind: ParseErrorKind::InvalidUnicodeEscape,
+                start: 21,
+                end: 23,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_integer_literals() {
+        let input = "-12 0xFF 0b1010 'A' '\\n' -0x10";
+        let expected = vec![
+            SpannedCommand {
+                command: Command::AddInteger(-12),
+                start: 0,
+                end: 3,
+            },
+            SpannedCommand {
+                command: Command::AddInteger(255),
+                start: 4,
+                end: 8,
+            },
+            SpannedCommand {
+                command: Command::AddInteger(10),
+                start: 9,
+                end: 15,
+            },
+            SpannedCommand {
+                command: Command::AddInteger(65),
+                start: 16,
+                end: 19,
+            },
+            SpannedCommand {
+                command: Command::AddInteger(10),
+                start: 20,
+                end: 24,
+            },
+            SpannedCommand {
+                command: Command::AddInteger(-16),
+                start: 25,
+                end: 30,
+            },
+        ];
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_write_integer_literals() {
+        let input = "[-1][0x7f][0b11][']'][-9223372036854775808]";
+        let expected = vec![
+            SpannedCommand {
+                command: Command::WriteInteger(-1),
+                start: 0,
+                end: 4,
+            },
+            SpannedCommand {
+                command: Command::WriteInteger(127),
+                start: 4,
+                end: 10,
+            },
+            SpannedCommand {
+                command: Command::WriteInteger(3),
+                start: 10,
+                end: 16,
+            },
+            SpannedCommand {
+                command: Command::WriteInteger(93),
+                start: 16,
+                end: 21,
+            },
+            SpannedCommand {
+                command: Command::WriteInteger(i64::MIN),
+                start: 21,
+                end: 43,
+            },
+        ];
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_subtract_is_not_negative_literal() {
+        let input = "-3> - 3";
+        let expected = vec![
+            SpannedCommand {
+                command: Command::RightSubtract(Some(3)),
+                start: 0,
+                end: 3,
+            },
+            SpannedCommand {
+                command: Command::Decrement,
+                start: 4,
+                end: 5,
+            },
+            SpannedCommand {
+                command: Command::AddInteger(3),
+                start: 6,
+                end: 7,
+            },
+        ];
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_right_offsets_are_decimal() {
+        let expected = vec![SpannedCommand {
+            command: Command::RightSubtract(Some(16)),
+            start: 0,
+            end: 4,
+        }];
+        assert_eq!(parse("-16>"), Ok(expected));
+        let expected = ParseError {
+            kind: ParseErrorKind::Expected('>'),
+            start: 0,
+            end: 2,
+        };
+        assert_eq!(parse("-0x10>").unwrap_err().first(), Some(&expected));
+        assert_eq!(parse("+0x10>").unwrap_err().first(), Some(&expected));
+        assert_eq!(parse("-0b1>").unwrap_err().first(), Some(&expected));
+    }
+
+    #[test]
+    fn test_parse_integer_literal_errors() {
+        let input = "9223372036854775808 [0x1FFFFFFFFFFFFFFFF] 0xZZ 'ab' []";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::IntegerOverflow,
+                start: 0,
+                end: 19,
+            },
+            ParseError {
+                kind: ParseErrorKind::IntegerOverflow,
+                start: 21,
+                end: 40,
+            },
+            ParseError {
+                kind: ParseErrorKind::InvalidNumber,
+                start: 42,
+                end: 46,
+            },
+            ParseError {
+                kind: ParseErrorKind::InvalidCharLiteral,
+                start: 47,
+                end: 51,
+            },
+            ParseError {
+                kind: ParseErrorKind::InvalidNumber,
+                start: 52,
+                end: 54,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
+    }
+
+    #[test]
+    fn test_parse_float_literals() {
+        let input = "1.25 -0.5 1e3 2.5E-1 [6.02e23] 3.";
+        let expected = vec![
+            SpannedCommand {
+                command: Command::AddFloat(1.25),
+                start: 0,
+                end: 4,
+            },
+            SpannedCommand {
+                command: Command::AddFloat(-0.5),
+                start: 5,
+                end: 9,
+            },
+            SpannedCommand {
+                command: Command::AddFloat(1e3),
+                start: 10,
+                end: 13,
+            },
+            SpannedCommand {
+                command: Command::AddFloat(0.25),
+                start: 14,
+                end: 20,
+            },
+            SpannedCommand {
+                command: Command::WriteFloat(6.02e23),
+                start: 21,
+                end: 30,
+            },
+            SpannedCommand {
+                command: Command::AddInteger(3),
+                start: 31,
+                end: 32,
+            },
+            SpannedCommand {
+                command: Command::OutputNumber,
+                start: 32,
+                end: 33,
+            },
+        ];
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_float_overflow() {
+        let input = "1e400";
+        let expected = vec![
+            ParseError {
+                kind: ParseErrorKind::FloatOverflow,
+                start: 0,
+                end: 5,
+            },
+        ];
+        assert_eq!(parse(input), Err(expected));
     }
 }
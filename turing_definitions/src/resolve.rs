@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+
+use crate::ast::{Command, SpannedCommand};
+use crate::parser::{ParseError, ParseErrorKind};
+
+/// Checks that every function the program calls or looks up with `getfun` is defined, before it runs.
+///
+/// A name is defined by a `fun` anywhere in the program, however deeply nested, or by being in `known`, such as
+/// functions defined by earlier REPL entries. A `fun` inside a block is only registered when it runs, so a name it
+/// defines can still be missing when the program gets to it, which the machine reports as an unknown function.
+pub fn resolve<'a>(program: &[SpannedCommand<'a>], known: &[&'a str]) -> Result<(), Vec<ParseError>> {
+    let mut defined: HashSet<&str> = known.iter().copied().collect();
+    collect_definitions(program, &mut defined);
+
+    let mut errors = Vec::new();
+    check_names(program, &defined, &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Adds the name of every function defined in `commands`, however deeply nested.
+fn collect_definitions<'a>(commands: &[SpannedCommand<'a>], defined: &mut HashSet<&'a str>) {
+    for command in commands {
+        if let Command::FunctionDefinition(name, _) = &command.command {
+            defined.insert(name);
+        }
+        for block in blocks(&command.command) {
+            collect_definitions(block, defined);
+        }
+    }
+}
+
+fn check_names(commands: &[SpannedCommand], defined: &HashSet<&str>, errors: &mut Vec<ParseError>) {
+    for command in commands {
+        if let Command::FunctionCall(name) | Command::GetFunction(name) = &command.command {
+            if !defined.contains(name) {
+                errors.push(ParseError {
+                    kind: ParseErrorKind::UndefinedFunction(name.to_string()),
+                    start: command.start,
+                    end: command.end,
+                });
+            }
+        }
+        for block in blocks(&command.command) {
+            check_names(block, defined, errors);
+        }
+    }
+}
+
+/// The bodies nested inside a command.
+fn blocks<'c, 'a>(command: &'c Command<'a>) -> Vec<&'c [SpannedCommand<'a>]> {
+    match command {
+        Command::If(if_commands, else_commands) => {
+            let mut blocks = vec![if_commands.as_slice()];
+            blocks.extend(else_commands.as_deref());
+            blocks
+        }
+        Command::While(commands) | Command::Loop(commands) | Command::FunctionDefinition(_, commands) => vec![commands.as_slice()],
+        _ => Vec::new(),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    /// The undefined names in `program`, each with the source its error points at.
+    fn undefined<'p>(program: &'p str, known: &[&str]) -> Vec<(String, &'p str)> {
+        let commands = parse(program).unwrap();
+        match resolve(&commands, known) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter()
+                .map(|error| {
+                    let ParseErrorKind::UndefinedFunction(name) = error.kind else {
+                        panic!("unexpected error {:?}", error);
+                    };
+                    (name, &program[error.start..error.end])
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_defined_later() {
+        assert_eq!(undefined("greet fun greet \"hi\" end getfun greet call", &[]), []);
+        assert_eq!(undefined("fun even if - odd end end fun odd if - even end end", &[]), []);
+    }
+
+    #[test]
+    fn test_nested_definitions() {
+        assert_eq!(undefined("fun outer fun inner end end outer inner", &[]), []);
+        assert_eq!(undefined("3 loop fun inner 9 . end - end inner getfun inner", &[]), []);
+        assert_eq!(undefined("fun outer fun inner inner end inner end outer", &[]), []);
+        assert_eq!(undefined("fun a b end fun b fun c 7 . end end a c", &[]), []);
+        assert_eq!(undefined("fun outer fun inner 5 . end end fun use inner end outer use", &[]), []);
+        // Whether a nested definition has run by the time its name is used is left to the machine.
+        assert_eq!(undefined("fun outer fun inner end end inner outer", &[]), []);
+        assert_eq!(undefined("if 0 fun never end end never", &[]), []);
+    }
+
+    #[test]
+    fn test_undefined() {
+        assert_eq!(undefined("fun f g end\nif missing end getfun gone", &[]), [
+            (String::from("g"), "g"),
+            (String::from("missing"), "missing"),
+            (String::from("gone"), "getfun gone"),
+        ]);
+    }
+
+    #[test]
+    fn test_known() {
+        assert_eq!(undefined("earlier later fun later end", &["earlier"]), []);
+        assert_eq!(undefined("earlier", &[]), [(String::from("earlier"), "earlier")]);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{Direction, Number, Tape, TapeError};
+
+
+/// A tape that extends without bound in both directions, so the head may move to negative cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfiniteTape<T> {
+    cells: VecDeque<T>,
+    /// The position of cell 0 in `cells`.
+    origin: usize,
+}
+
+impl<T: Number> InfiniteTape<T> {
+    pub fn new() -> InfiniteTape<T> {
+        InfiniteTape {
+            cells: VecDeque::from([T::default()]),
+            origin: 0,
+        }
+    }
+
+    /// The lowest index currently allocated.
+    pub fn start(&self) -> isize {
+        -(self.origin as isize)
+    }
+
+    fn position(&self, index: isize) -> usize {
+        (index + self.origin as isize) as usize
+    }
+}
+
+impl<T: Number> Default for InfiniteTape<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Number> Tape<T> for InfiniteTape<T> {
+    fn get(&self, index: isize) -> T {
+        self.cells[self.position(index)]
+    }
+
+    fn set(&mut self, index: isize, value: T) -> Result<(), TapeError> {
+        let position = self.position(index);
+        self.cells[position] = value;
+        Ok(())
+    }
+
+    fn grow(&mut self, direction: Direction) -> Result<(), TapeError> {
+        match direction {
+            Direction::Left => {
+                self.cells.push_front(T::default());
+                self.origin += 1;
+            }
+            Direction::Right => self.cells.push_back(T::default()),
+        }
+        Ok(())
+    }
+
+    /// Allocates every missing cell at once, failing rather than aborting when they do not fit in memory.
+    fn reach(&mut self, index: isize) -> Result<(), TapeError> {
+        let cells = self.cells_to_reach(index);
+        self.cells.try_reserve(cells).map_err(|_| TapeError::OutOfMemory)?;
+        self.cells.resize(self.cells.len() + cells, T::default());
+        if index < 0 {
+            self.cells.rotate_right(cells);
+            self.origin += cells;
+        }
+        Ok(())
+    }
+
+    fn in_bounds(&self, index: isize) -> bool {
+        index >= self.start() && index < self.start() + self.cells.len() as isize
+    }
+
+    fn allocated(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cells_to_reach(&self, index: isize) -> usize {
+        let end = self.start() + self.cells.len() as isize;
+        if index < self.start() {
+            self.start().abs_diff(index)
+        } else if index >= end {
+            index.abs_diff(end) + 1
+        } else {
+            0
+        }
+    }
+}
+
+
+/// A tape that stores only the cells holding a non-default value, so far jumps cost no memory.
+///
+/// Every index is in bounds, in both directions.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SparseTape<T> {
+    cells: BTreeMap<isize, T>,
+}
+
+impl<T: Number> SparseTape<T> {
+    pub fn new() -> SparseTape<T> {
+        SparseTape {
+            cells: BTreeMap::new(),
+        }
+    }
+
+    /// The cells holding a non-default value, in index order.
+    pub fn cells(&self) -> impl Iterator<Item = (isize, T)> + '_ {
+        self.cells.iter().map(|(index, value)| (*index, *value))
+    }
+}
+
+impl<T: Number> Tape<T> for SparseTape<T> {
+    fn get(&self, index: isize) -> T {
+        self.cells.get(&index).copied().unwrap_or_default()
+    }
+
+    fn set(&mut self, index: isize, value: T) -> Result<(), TapeError> {
+        if value == T::default() {
+            self.cells.remove(&index);
+        } else {
+            self.cells.insert(index, value);
+        }
+        Ok(())
+    }
+
+    fn grow(&mut self, _direction: Direction) -> Result<(), TapeError> {
+        Ok(())
+    }
+
+    fn in_bounds(&self, _index: isize) -> bool {
+        true
+    }
+
+    fn allocated(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cells_to_reach(&self, _index: isize) -> usize {
+        0
+    }
+}
+
+
+/// Wraps another tape and fails with [`TapeError::LimitExceeded`] instead of holding more than `max_cells` cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundedTape<P> {
+    tape: P,
+    max_cells: usize,
+}
+
+impl<P> BoundedTape<P> {
+    pub fn new(tape: P, max_cells: usize) -> BoundedTape<P> {
+        BoundedTape {
+            tape,
+            max_cells,
+        }
+    }
+
+    /// Limits the tape to as many cells of `T` as fit in `max_bytes`, counting only the cell values.
+    pub fn with_max_bytes<T>(tape: P, max_bytes: usize) -> BoundedTape<P> {
+        Self::new(tape, max_bytes / std::mem::size_of::<T>().max(1))
+    }
+
+    pub fn max_cells(&self) -> usize {
+        self.max_cells
+    }
+
+    pub fn into_inner(self) -> P {
+        self.tape
+    }
+}
+
+impl<T: Number, P: Tape<T>> Tape<T> for BoundedTape<P> {
+    fn get(&self, index: isize) -> T {
+        self.tape.get(index)
+    }
+
+    fn set(&mut self, index: isize, value: T) -> Result<(), TapeError> {
+        let previous = self.tape.get(index);
+        self.tape.set(index, value)?;
+        // Sparse tapes allocate on write rather than on growth.
+        if self.tape.allocated() > self.max_cells {
+            self.tape.set(index, previous)?;
+            return Err(TapeError::LimitExceeded(self.max_cells));
+        }
+        Ok(())
+    }
+
+    fn grow(&mut self, direction: Direction) -> Result<(), TapeError> {
+        if self.tape.allocated() >= self.max_cells {
+            return Err(TapeError::LimitExceeded(self.max_cells));
+        }
+        self.tape.grow(direction)
+    }
+
+    /// Fails before growing at all if reaching `index` would take the tape past its limit.
+    fn reach(&mut self, index: isize) -> Result<(), TapeError> {
+        if self.tape.allocated().saturating_add(self.tape.cells_to_reach(index)) > self.max_cells {
+            return Err(TapeError::LimitExceeded(self.max_cells));
+        }
+        self.tape.reach(index)
+    }
+
+    fn in_bounds(&self, index: isize) -> bool {
+        self.tape.in_bounds(index)
+    }
+
+    fn allocated(&self) -> usize {
+        self.tape.allocated()
+    }
+
+    fn cells_to_reach(&self, index: isize) -> usize {
+        self.tape.cells_to_reach(index)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Operation, OverflowMode};
+
+    #[test]
+    fn test_infinite_tape_grows_left() {
+        let mut tape = InfiniteTape::<i64>::new();
+        tape.set(0, 7).unwrap();
+        tape.reach(-3).unwrap();
+        assert_eq!(tape.start(), -3);
+        assert!(tape.in_bounds(-3) && tape.in_bounds(0) && !tape.in_bounds(1));
+        tape.set(-3, 5).unwrap();
+        assert_eq!(tape.get(-3), 5);
+        assert_eq!(tape.get(-1), 0);
+        assert_eq!(tape.get(0), 7);
+    }
+
+    #[test]
+    fn test_infinite_tape_operate_left_of_origin() {
+        let mut tape = InfiniteTape::<i64>::new();
+        tape.set(0, 4).unwrap();
+        tape.operate(0, Direction::Left, Some(2), Operation::Add, OverflowMode::Wrapping).unwrap();
+        assert_eq!(tape.get(-2), 4);
+        tape.reach(2).unwrap();
+        assert!(tape.in_bounds(2));
+        assert_eq!(tape.get(2), 0);
+    }
+
+    #[test]
+    fn test_sparse_tape_stores_only_set_cells() {
+        let mut tape = SparseTape::<i64>::new();
+        tape.reach(1_000_000).unwrap();
+        tape.reach(-1_000_000).unwrap();
+        assert_eq!(tape.cells().count(), 0);
+        tape.set(1_000_000, 3).unwrap();
+        tape.set(-5, 2).unwrap();
+        assert_eq!(tape.get(1_000_000), 3);
+        assert_eq!(tape.get(7), 0);
+        assert_eq!(tape.cells().collect::<Vec<_>>(), vec![(-5, 2), (1_000_000, 3)]);
+        tape.set(-5, 0).unwrap();
+        assert_eq!(tape.cells().collect::<Vec<_>>(), vec![(1_000_000, 3)]);
+    }
+
+    #[test]
+    fn test_sparse_tape_operate() {
+        let mut tape = SparseTape::<i64>::new();
+        tape.set(0, 6).unwrap();
+        tape.operate(0, Direction::Right, Some(1 << 40), Operation::Add, OverflowMode::Wrapping).unwrap();
+        assert_eq!(tape.get(1 << 40), 6);
+        tape.decrement(1 << 40, OverflowMode::Wrapping).unwrap();
+        assert_eq!(tape.get(1 << 40), 5);
+    }
+
+    #[test]
+    fn test_sparse_tape_ends() {
+        let mut tape = SparseTape::<i64>::new();
+        tape.set(isize::MAX, 2).unwrap();
+        tape.set(isize::MIN, 3).unwrap();
+        assert_eq!(tape.operate(isize::MAX, Direction::Left, Some(usize::MAX), Operation::Add, OverflowMode::Wrapping), Ok(()));
+        assert_eq!(tape.get(isize::MIN), 5);
+        assert_eq!(tape.operate(isize::MAX, Direction::Right, None, Operation::Add, OverflowMode::Wrapping), Err(TapeError::OutOfRange));
+        assert_eq!(tape.operate(isize::MIN, Direction::Left, None, Operation::Divide, OverflowMode::Wrapping), Err(TapeError::OutOfRange));
+        assert_eq!(tape.operate(0, Direction::Right, Some(usize::MAX), Operation::Add, OverflowMode::Wrapping), Err(TapeError::OutOfRange));
+        assert_eq!(tape.cells().collect::<Vec<_>>(), vec![(isize::MIN, 5), (isize::MAX, 2)]);
+    }
+
+    #[test]
+    fn test_bounded_tape_stops_growing() {
+        let mut tape = BoundedTape::new(vec![0i64], 4);
+        assert_eq!(tape.reach(3), Ok(()));
+        assert_eq!(tape.reach(4), Err(TapeError::LimitExceeded(4)));
+        assert_eq!(tape.allocated(), 4);
+        assert_eq!(tape.operate(0, Direction::Right, Some(10), Operation::Add, OverflowMode::Wrapping), Err(TapeError::LimitExceeded(4)));
+    }
+
+    #[test]
+    fn test_bounded_tape_checks_before_growing() {
+        let mut tape = BoundedTape::new(InfiniteTape::<i64>::new(), 4);
+        assert_eq!(tape.reach(-10), Err(TapeError::LimitExceeded(4)));
+        assert_eq!(tape.reach(isize::MAX), Err(TapeError::LimitExceeded(4)));
+        assert_eq!(tape.allocated(), 1);
+        assert_eq!(tape.reach(-3), Ok(()));
+        assert_eq!(tape.allocated(), 4);
+    }
+
+    #[test]
+    fn test_reach_out_of_memory() {
+        let mut tape = InfiniteTape::<i64>::new();
+        assert_eq!(tape.reach(isize::MAX), Err(TapeError::OutOfMemory));
+        assert_eq!(tape.reach(isize::MIN), Err(TapeError::OutOfMemory));
+        assert_eq!(tape.allocated(), 1);
+        let mut tape = vec![0i64];
+        assert_eq!(tape.reach(isize::MAX), Err(TapeError::OutOfMemory));
+        assert_eq!(tape.len(), 1);
+    }
+
+    #[test]
+    fn test_bounded_sparse_tape_limits_writes() {
+        let mut tape = BoundedTape::with_max_bytes::<i64>(SparseTape::<i64>::new(), 16);
+        assert_eq!(tape.max_cells(), 2);
+        tape.set(-100, 1).unwrap();
+        tape.set(100, 2).unwrap();
+        assert_eq!(tape.set(5, 3), Err(TapeError::LimitExceeded(2)));
+        assert_eq!(tape.get(5), 0);
+        tape.set(100, 0).unwrap();
+        assert_eq!(tape.set(5, 3), Ok(()));
+        assert_eq!(tape.into_inner().cells().collect::<Vec<_>>(), vec![(-100, 1), (5, 3)]);
+    }
+
+    #[test]
+    fn test_vec_tape_cannot_grow_left() {
+        let mut tape = vec![0i64];
+        assert_eq!(tape.reach(-1), Err(TapeError::Underflow));
+        assert_eq!(tape.reach(2), Ok(()));
+        assert_eq!(tape.len(), 3);
+    }
+}